@@ -0,0 +1,32 @@
+//! Print the watchlist of a database, oldest first:
+//!
+//! cargo run --example watchlist -- ~/.local/share/mtracker/db.txt
+
+use std::path::Path;
+
+use mtracker::{ListOptions, Query, Repo};
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: watchlist <db>");
+        std::process::exit(2);
+    };
+
+    let repo = match Repo::new(Path::new(&path)) {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let mut items = Query::parse("watchlist").filter(&repo);
+    items.sort_by_key(|item| item.year);
+
+    let options = ListOptions {
+        tags: true,
+        ..Default::default()
+    };
+    for item in items {
+        println!("{}", item.as_line(&options));
+    }
+}
@@ -1,7 +1,11 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use clap::{ArgMatches, Command};
 
-use crate::{arg_util, args};
+use crate::{
+    arg_util, args,
+    error::Error,
+    media::{repo::Repo, tag},
+};
 
 pub fn command() -> Command {
     Command::new("add")
@@ -12,27 +16,44 @@ pub fn command() -> Command {
         .arg(args::year())
         .arg(args::tag().help("Tag(s) to add, comma-separated"))
         .arg(args::note())
+        .arg(args::create())
 }
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
     let mut repo = arg_util::repo_from_matches(matches)?;
+    apply(&mut repo, matches)?;
+    Ok(repo.write()?)
+}
+
+/// Add an item and/or tags and a note, without saving
+pub fn apply(repo: &mut Repo, matches: &ArgMatches) -> Result<()> {
     let handle = arg_util::handle_from_matches(matches)?.unwrap();
     let tags = arg_util::tags_from_matches(matches);
     let note = arg_util::note_from_matches(matches)?;
+    let create = *matches.get_one::<bool>("CREATE").unwrap_or(&false);
+
+    // Look up the item, unless a new one should be created
+    let existing = match create {
+        true => repo.contains(&handle).then(|| handle.clone()),
+        false => arg_util::resolve_new(repo, &handle)?,
+    };
 
     // Report error when just adding an existing item
-    let media = repo.get(&handle);
-    if media.is_some() && tags.is_empty() {
-        return Err(anyhow!("item already exists: {handle}"));
+    if let (Some(h), true) = (&existing, tags.is_empty()) {
+        return Err(Error::AlreadyExists(h.clone()).into());
     }
 
-    let media = match media {
-        Some(m) => m,
-        None => repo.get_or_create(&handle)?,
+    let handle = match existing {
+        Some(h) => h,
+        None => {
+            arg_util::add(repo, &handle)?;
+            handle
+        }
     };
+    let media = repo.get(&handle).unwrap();
 
     // Add tags
-    for tag in tags {
+    for tag in tags.into_iter().map(|t| tag::normalize(t)) {
         if media.has_tag(tag) {
             eprintln!("Tag already exists: {tag}");
         } else {
@@ -47,11 +68,11 @@ pub fn handle(matches: &ArgMatches) -> Result<()> {
             media.note = n;
             println!("Added note to {handle}: {}", media.note);
         } else {
-            return Err(anyhow!(
-                "item already has a note. Run 'mtracker edit \"{handle}\"' to modify it."
-            ));
+            let message =
+                format!("item already has a note. Run 'mtracker edit \"{handle}\"' to modify it.");
+            return Err(Error::invalid("note", message).into());
         }
     }
 
-    repo.write()
+    Ok(())
 }
@@ -1,9 +1,20 @@
-use std::path::PathBuf;
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
 
-use crate::media::{handle, repo};
+use crate::{
+    config,
+    error::Error,
+    media::{
+        handle::{self, Handle},
+        repo::{self, Lookup, Repo},
+        Media,
+    },
+};
 
 pub fn terms_from_matches(matches: &ArgMatches) -> Vec<&String> {
     match matches.get_many::<String>("TERM") {
@@ -12,9 +23,38 @@ pub fn terms_from_matches(matches: &ArgMatches) -> Vec<&String> {
     }
 }
 
+/// Database path from --db or --profile, falling back to the config
+pub fn db_from_matches(matches: &ArgMatches) -> Result<PathBuf> {
+    if let Some(path) = matches.get_one::<String>("DB") {
+        return Ok(PathBuf::from(path));
+    }
+    match matches.get_one::<String>("PROFILE") {
+        Some(profile) => config::get().profile_db(profile),
+        None => Ok(config::get().db_path()),
+    }
+}
+
+pub fn lenient_from_matches(matches: &ArgMatches) -> bool {
+    *matches.get_one::<bool>("LENIENT").unwrap_or(&false)
+}
+
+/// Open the database, reporting skipped entries with --lenient
 pub fn repo_from_matches(matches: &ArgMatches) -> Result<repo::Repo> {
-    let path = PathBuf::from(matches.get_one::<String>("DB").unwrap());
-    repo::Repo::new(&path)
+    open_repo(&db_from_matches(matches)?, lenient_from_matches(matches))
+}
+
+pub fn open_repo(path: &Path, lenient: bool) -> Result<repo::Repo> {
+    let repo = repo::Repo::open(path, lenient).map_err(|e| match e {
+        Error::InvalidEntries(_) => {
+            let message = format!("{e}\n\nUse --lenient to skip invalid entries.");
+            anyhow::Error::from(e).context(message)
+        }
+        e => e.into(),
+    })?;
+    for error in repo.errors() {
+        eprintln!("Skipped invalid entry: {error}");
+    }
+    Ok(repo)
 }
 
 pub fn tags_from_matches(matches: &ArgMatches) -> Vec<&String> {
@@ -44,7 +84,7 @@ pub fn note_from_matches(matches: &ArgMatches) -> Result<Option<String>> {
     match matches.try_get_one::<String>("NOTE")? {
         Some(note) => {
             if note.contains('\n') {
-                Err(anyhow!("note should be a single line"))
+                Err(Error::invalid("note", "note should be a single line").into())
             } else {
                 Ok(Some(note.to_string()))
             }
@@ -52,3 +92,150 @@ pub fn note_from_matches(matches: &ArgMatches) -> Result<Option<String>> {
         None => Ok(None),
     }
 }
+
+/// Resolve a handle the user entered to an existing item. Returns `None` if a
+/// new item should be created, which is only the case if `allow_create` is set.
+pub fn resolve(repo: &Repo, handle: &Handle, allow_create: bool) -> Result<Option<Handle>> {
+    resolve_lookup(repo, handle, repo.lookup(handle), allow_create)
+}
+
+/// Like `resolve` with `allow_create`, for adding items: items that only
+/// contain the name are listed as a warning, and a new item is created.
+pub fn resolve_new(repo: &Repo, handle: &Handle) -> Result<Option<Handle>> {
+    match repo.lookup(handle) {
+        Lookup::NotFound(similar) => {
+            if !similar.is_empty() {
+                let list: String = similar
+                    .iter()
+                    .map(|&i| format!("\n  {}", repo.get_by_index(i).handle()))
+                    .collect();
+                eprintln!("Similar items exist:{list}");
+            }
+            Ok(None)
+        }
+        lookup => resolve_lookup(repo, handle, lookup, true),
+    }
+}
+
+fn resolve_lookup(
+    repo: &Repo,
+    handle: &Handle,
+    lookup: Lookup,
+    allow_create: bool,
+) -> Result<Option<Handle>> {
+    let (candidates, similar) = match lookup {
+        Lookup::Found(i) => return Ok(Some(repo.get_by_index(i).handle())),
+        Lookup::Ambiguous(c) => (c, false),
+
+        // Nothing similar
+        Lookup::NotFound(c) if c.is_empty() => {
+            return match allow_create {
+                true => Ok(None),
+                false => Err(Error::NotFound(handle.clone()).into()),
+            };
+        }
+
+        // Items containing the name are only suggested, even a single one
+        Lookup::NotFound(c) => (c, true),
+    };
+
+    let candidates: Vec<Handle> =
+        candidates.into_iter().map(|i| repo.get_by_index(i).handle()).collect();
+    choose(handle, &candidates, allow_create, similar)
+}
+
+/// Resolve a handle to an existing item, creating a new one if there is none.
+/// With `create`, only exact matches are used.
+pub fn get_or_create(repo: &mut Repo, handle: &Handle, create: bool) -> Result<Handle> {
+    let existing = if create {
+        repo.contains(handle).then(|| handle.clone())
+    } else {
+        resolve(repo, handle, true)?
+    };
+
+    match existing {
+        Some(h) => Ok(h),
+        // Most likely a mistyped ID rather than a name starting with '#'
+        None if !create && handle.id().is_some() => {
+            let e = Error::NotFound(handle.clone());
+            let message = format!("{e}\nUse --create to add a new item.");
+            Err(anyhow::Error::from(e).context(message))
+        }
+        None => {
+            add(repo, handle)?;
+            Ok(handle.clone())
+        }
+    }
+}
+
+/// Add a new item and tell the user its ID
+pub fn add(repo: &mut Repo, handle: &Handle) -> Result<()> {
+    repo.add(Media::from_handle(handle))?;
+    let id = repo.get_by_index(repo.len() - 1).id.as_deref().unwrap_or_default();
+    println!("Added new item #{id}: {handle}");
+    Ok(())
+}
+
+// Let the user pick one of several items, or fail with a list of them if not
+// running interactively. `similar` items only contain the name, rather than
+// matching it.
+fn choose(
+    handle: &Handle,
+    candidates: &[Handle],
+    allow_create: bool,
+    similar: bool,
+) -> Result<Option<Handle>> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        let (e, mut message) = match similar {
+            true => {
+                let e = Error::NotFound(handle.clone());
+                let list: String = candidates.iter().map(|h| format!("\n  {h}")).collect();
+                let message = format!("{e}\nSimilar items:{list}");
+                (e, message)
+            }
+            false => {
+                let e = Error::Ambiguous {
+                    handle: handle.clone(),
+                    candidates: candidates.to_vec(),
+                };
+                let message = e.to_string();
+                (e, message)
+            }
+        };
+        if allow_create {
+            message += "\nUse --create to add a new item.";
+        }
+        return Err(anyhow::Error::from(e).context(message));
+    }
+
+    match similar {
+        true => eprintln!("No item matches \"{handle}\", but these are similar:"),
+        false => eprintln!("\"{handle}\" matches several items:"),
+    }
+    if allow_create {
+        eprintln!("  0) Create new item: {handle}");
+    }
+    for (i, candidate) in candidates.iter().enumerate() {
+        eprintln!("  {}) {candidate}", i + 1);
+    }
+
+    loop {
+        eprint!("Choose an item (empty to abort): ");
+        io::stderr().flush()?;
+
+        let mut input = String::new();
+        io::stdin().lock().read_line(&mut input)?;
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(anyhow!("aborted"));
+        }
+
+        match input.parse::<usize>() {
+            Ok(0) if allow_create => return Ok(None),
+            Ok(n) if (1..=candidates.len()).contains(&n) => {
+                return Ok(Some(candidates[n - 1].clone()))
+            }
+            _ => eprintln!("Invalid choice: {input}"),
+        }
+    }
+}
@@ -1,8 +1,41 @@
-use clap::{crate_name, Arg};
+use clap::Arg;
+use clap_complete::ArgValueCandidates;
+
+use crate::completions;
+
+/// Explanation of search terms, shown by --help and in man pages
+pub const TERM_HELP: &str = "\
+Search terms:
+  <tag>          Items with this tag (or one of its descendants)
+  <text>         Items whose name contains <text>
+  rated          Items that have a rating
+  unrated        Items without a rating
+  ++             Items with a rating of at least 2
+  ---            Items with at least 3 minuses
+  ++-            Items with an exact rating of 2
+  <year>         Items released in <year>
+  <year>-<year>  Items released between the two years
+  -<year>        Items released before or in <year>
+  <year>-        Items released after or in <year>
+  !<term>        Exclude items matching <term>
+
+All terms must match.";
+
+/// Explanation of storage kinds, shown by --help and in man pages
+pub const KIND_HELP: &str = "\
+Kinds of storage:
+  text       A single text file (default)
+  dir        A directory with one text file per item
+  sqlite     An SQLite database, for very large libraries
+  json       A JSON file
+  yaml       A YAML file
+  toml       A TOML file
+  encrypted  A text file, encrypted with a passphrase";
 
 pub fn identifier() -> Arg {
     Arg::new("IDENTIFIER")
         .required(true)
+        .add(ArgValueCandidates::new(completions::handles))
         .help("\"name (year)\" or \"name\"")
         .long_help(
             "The name (and optionally year of release) of the movie / series.
@@ -21,26 +54,46 @@ pub fn year() -> Arg {
 }
 
 pub fn term() -> Arg {
-    Arg::new("TERM").required(false).trailing_var_arg(true).num_args(0..)
+    Arg::new("TERM")
+        .required(false)
+        .trailing_var_arg(true)
+        .num_args(0..)
+        .add(ArgValueCandidates::new(completions::tags))
 }
 
 pub fn db() -> Arg {
-    // Get default db path
-    let mut path = dirs::data_dir().expect("failed to get user data directory");
-    path.push(format!("{}/db.txt", crate_name!()));
-
     Arg::new("DB")
         .required(false)
         .long("db")
-        .help("Database file")
+        .help("Database file or directory [default: from config or ~/.local/share/mtracker/db.txt]")
+        .global(true)
+}
+
+pub fn profile() -> Arg {
+    Arg::new("PROFILE")
+        .required(false)
+        .short('p')
+        .long("profile")
+        .env("MTRACKER_PROFILE")
+        .help("Database profile from the config")
+        .global(true)
+}
+
+pub fn lenient() -> Arg {
+    Arg::new("LENIENT")
+        .required(false)
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0)
+        .long("lenient")
+        .help("Skip invalid database entries instead of failing (they are kept when saving)")
         .global(true)
-        .default_value(path.into_os_string())
 }
 
 pub fn tag() -> Arg {
     Arg::new("TAG")
         .required(false)
         .num_args(0..)
+        .add(ArgValueCandidates::new(completions::tags))
         .short('t')
         .value_delimiter(',')
         .long("tag")
@@ -70,6 +123,15 @@ pub fn note_bool() -> Arg {
         .long("note")
 }
 
+pub fn seen_bool() -> Arg {
+    Arg::new("SEEN")
+        .required(false)
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0)
+        .short('s')
+        .long("seen")
+}
+
 pub fn tags_bool() -> Arg {
     Arg::new("TAGS")
         .required(false)
@@ -78,3 +140,24 @@ pub fn tags_bool() -> Arg {
         .short('g')
         .long("tags")
 }
+
+pub fn create() -> Arg {
+    Arg::new("CREATE")
+        .required(false)
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0)
+        .long("create")
+        .help("Create a new item instead of looking up similar ones")
+}
+
+pub fn kind() -> Arg {
+    Arg::new("TO").required(false).long("to").value_parser([
+        "text",
+        "dir",
+        "sqlite",
+        "json",
+        "yaml",
+        "toml",
+        "encrypted",
+    ])
+}
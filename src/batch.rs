@@ -0,0 +1,155 @@
+use std::{
+    io::{self, BufRead},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use clap::{parser::ValueSource, Arg, ArgMatches, Command};
+
+use crate::{add, arg_util, media::repo::Repo, rate, remove, rename, unrate};
+
+pub fn command() -> Command {
+    Command::new("batch")
+        .about("Run commands read from stdin, one per line")
+        .long_about(
+            "Run commands read from stdin, one per line
+
+Supported commands are add, rm, rate, unrate and mv. Arguments are quoted like in
+a shell. Empty lines and lines starting with '#' are ignored. Changes are only
+saved if all commands succeed. Options like --db apply to all lines, so they
+are given to batch itself.
+
+Example:
+  rate \"Pearl (2022)\" 8
+  add \"Aliens (1986)\" --tag watchlist,horror",
+        )
+        .arg(
+            Arg::new("FILE")
+                .required(false)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read commands from a file instead of stdin"),
+        )
+        .arg(
+            Arg::new("DRY_RUN")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("dry-run")
+                .help("Run all commands, but don't save changes"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches, root: Command) -> Result<()> {
+    let mut repo = arg_util::repo_from_matches(matches)?;
+
+    let lines: Vec<String> = match matches.get_one::<PathBuf>("FILE") {
+        Some(path) => std::fs::read_to_string(path)?.lines().map(String::from).collect(),
+        None => io::stdin().lock().lines().collect::<io::Result<_>>()?,
+    };
+
+    // Run all commands, even after a failure, to report all of them
+    let mut failures = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        if let Err(e) = run_line(&mut repo, &root, line) {
+            failures.push(format!("line {}: {e}", i + 1));
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow!(
+            "{} command(s) failed:\n{}\n\nNo changes saved.",
+            failures.len(),
+            failures.join("\n")
+        ));
+    }
+
+    if *matches.get_one::<bool>("DRY_RUN").unwrap_or(&false) {
+        println!("Dry run, no changes saved.");
+        return Ok(());
+    }
+
+    Ok(repo.write()?)
+}
+
+fn run_line(repo: &mut Repo, root: &Command, line: &str) -> Result<()> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+
+    let args = shlex::split(line).ok_or_else(|| anyhow!("unbalanced quotes"))?;
+    let matches = root
+        .clone()
+        .try_get_matches_from(std::iter::once(root.get_name().to_string()).chain(args))
+        .map_err(|e| anyhow!("{}", clap_error(&e)))?;
+
+    // All lines use the database of the batch command
+    let given = |m: &ArgMatches, id| m.value_source(id) == Some(ValueSource::CommandLine);
+    let sub = matches.subcommand().map_or(&matches, |(_, m)| m);
+    for (id, option) in [("DB", "--db"), ("PROFILE", "--profile"), ("LENIENT", "--lenient")] {
+        if given(&matches, id) || given(sub, id) {
+            return Err(anyhow!(
+                "{option} is not supported in batch mode, give it to batch instead"
+            ));
+        }
+    }
+
+    match matches.subcommand() {
+        Some(("add", m)) => add::apply(repo, m),
+        Some(("rm", m)) => remove::apply(repo, m),
+        Some(("rate", m)) => rate::apply(repo, m),
+        Some(("unrate", m)) => unrate::apply(repo, m),
+        Some(("mv", m)) => rename::apply(repo, m),
+        Some((name, _)) => Err(anyhow!("command not supported in batch mode: {name}")),
+        None => Err(anyhow!("command missing")),
+    }
+}
+
+// First line of a clap error, without usage and "error: " prefix
+fn clap_error(e: &clap::Error) -> String {
+    let rendered = e.render().to_string();
+    let first = rendered.lines().next().unwrap_or_default();
+    first.strip_prefix("error: ").unwrap_or(first).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_lines() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_runs_lines.txt");
+        std::fs::write(&path, "Pearl\nyear: 2022\ntags: watchlist").unwrap();
+        let mut repo = Repo::new(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let root = crate::command();
+        run_line(&mut repo, &root, "rate pearl 8").unwrap();
+        run_line(&mut repo, &root, "  # comment").unwrap();
+        run_line(&mut repo, &root, "add \"Aliens (1986)\" --tag horror").unwrap();
+        assert_eq!(repo.get_by_index(0).rating, Some(8));
+        assert!(repo.get_by_index(0).tags.is_empty());
+        assert_eq!(repo.get_by_index(1).tags, vec!["horror"]);
+
+        // Names that are part of another item's name are new items
+        run_line(&mut repo, &root, "add Alien").unwrap();
+        assert_eq!(repo.get_by_index(2).name, "Alien");
+        assert!(run_line(&mut repo, &root, "rate alie 7").is_err());
+
+        let error = run_line(&mut repo, &root, "rate \"Pearl").unwrap_err();
+        assert_eq!(error.to_string(), "unbalanced quotes");
+        let error = run_line(&mut repo, &root, "ls").unwrap_err();
+        assert_eq!(error.to_string(), "command not supported in batch mode: ls");
+        let error = run_line(&mut repo, &root, "rate pearl x").unwrap_err();
+        assert!(error.to_string().starts_with("invalid value 'x'"));
+
+        for line in ["--db other.txt rate pearl 9", "rate pearl 9 -p kids", "rm pearl --lenient"] {
+            let error = run_line(&mut repo, &root, line).unwrap_err();
+            assert!(error
+                .to_string()
+                .ends_with("is not supported in batch mode, give it to batch instead"));
+        }
+        assert_eq!(repo.get_by_index(0).rating, Some(8));
+    }
+}
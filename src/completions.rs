@@ -0,0 +1,84 @@
+use std::io;
+
+use anyhow::{anyhow, Result};
+use clap::{crate_name, Arg, ArgMatches, Command};
+use clap_complete::{env::Shells, CompletionCandidate};
+
+use crate::{
+    config,
+    media::{
+        repo::Repo,
+        storage::{encrypted, Kind},
+    },
+};
+
+pub fn command() -> Command {
+    Command::new("completions")
+        .about("Print shell completion script")
+        .long_about(
+            "Print shell completion script
+
+Examples:
+- bash: echo 'source <(mtracker completions bash)' >> ~/.bashrc
+- zsh:  echo 'source <(mtracker completions zsh)' >> ~/.zshrc
+- fish: mtracker completions fish > ~/.config/fish/completions/mtracker.fish",
+        )
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("SHELL")
+                .required(true)
+                .value_parser(Shells::builtins().names().collect::<Vec<_>>())
+                .help("Shell to print the completion script for"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let shell = matches.get_one::<String>("SHELL").unwrap();
+    let shells = Shells::builtins();
+    let completer = shells.completer(shell).ok_or_else(|| anyhow!("unsupported shell: {shell}"))?;
+
+    // The script calls back into mtracker with COMPLETE=<shell> to get candidates
+    let name = crate_name!();
+    completer.write_registration("COMPLETE", name, name, name, &mut io::stdout())?;
+    Ok(())
+}
+
+/// Handles of all items, e.g.: "Pearl (2022)"
+pub fn handles() -> Vec<CompletionCandidate> {
+    let Some(repo) = repo() else {
+        return vec![];
+    };
+    repo.get_all()
+        .into_iter()
+        .map(|m| CompletionCandidate::new(m.handle().to_string()))
+        .collect()
+}
+
+/// All tags in use
+pub fn tags() -> Vec<CompletionCandidate> {
+    let Some(repo) = repo() else {
+        return vec![];
+    };
+    let mut tags: Vec<&String> = repo.get_all().into_iter().flat_map(|m| &m.tags).collect();
+    tags.sort();
+    tags.dedup();
+    tags.into_iter().map(CompletionCandidate::new).collect()
+}
+
+// Completion happens before arguments are parsed, so --db and --profile are
+// not known yet. Use the profile from the environment or the default database.
+fn repo() -> Option<Repo> {
+    let config = config::Config::load().unwrap_or_default();
+    let path = match std::env::var("MTRACKER_PROFILE") {
+        Ok(profile) => config.profile_db(&profile).ok()?,
+        Err(_) => config.db_path(),
+    };
+
+    // Never ask for a passphrase while completing
+    if Kind::detect(&path) == Kind::Encrypted
+        && std::env::var_os(encrypted::PASSPHRASE_VAR).is_none()
+    {
+        return None;
+    }
+    Repo::new(&path).ok()
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use clap::{crate_name, ArgMatches, Command};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Name of the profile that uses the default database
+pub const DEFAULT_PROFILE: &str = "default";
+
+const TEMPLATE: &str = "\
+# mtracker configuration. Uncomment and change the settings you need.
+
+# Database file
+# db = \"~/.local/share/mtracker/db.txt\"
+
+# Tag that puts items on the watchlist
+# watchlist_tag = \"watchlist\"
+
+# Highest possible rating. By default, the highest rated item determines the scale.
+# rating_scale = 10
+
+# Format of dates when listing items, see:
+# https://docs.rs/chrono/latest/chrono/format/strftime/index.html
+# date_format = \"%Y-%m-%d\"
+
+# Default options for 'mtracker ls'
+[ls]
+# note = false
+# tags = false
+# seen = false
+
+# Interactive terminal interface
+[tui]
+# show_tags = true
+# show_notes = true
+# confirm_delete = true
+
+# Additional databases, selected with 'mtracker --profile kids' or the
+# MTRACKER_PROFILE environment variable
+# [profiles.kids]
+# db = \"~/.local/share/mtracker/kids.txt\"
+";
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db: Option<PathBuf>,
+    pub watchlist_tag: String,
+    pub rating_scale: Option<u8>,
+    pub date_format: String,
+    pub ls: ListConfig,
+    pub tui: TuiConfig,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListConfig {
+    pub note: bool,
+    pub tags: bool,
+    pub seen: bool,
+}
+
+/// A named database, selected with --profile
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub db: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    pub show_tags: bool,
+    pub show_notes: bool,
+    pub confirm_delete: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db: None,
+            watchlist_tag: "watchlist".into(),
+            rating_scale: None,
+            date_format: "%Y-%m-%d".into(),
+            ls: ListConfig::default(),
+            tui: TuiConfig::default(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig {
+            show_tags: true,
+            show_notes: true,
+            confirm_delete: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(input: &str) -> Result<Self> {
+        let config: Self = toml::from_str(input).map_err(|e| anyhow!("invalid config: {e}"))?;
+
+        // Formatting a date with an invalid format panics, so check it up front
+        if StrftimeItems::new(&config.date_format).any(|item| item == Item::Error) {
+            let message = format!("invalid config: invalid date_format: {}", config.date_format);
+            return Err(Error::invalid("date_format", message).into());
+        }
+        Ok(config)
+    }
+
+    /// Read config from the user's config directory, if the file exists
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content).map_err(|e| {
+                let message = format!("{}: {e}", path.to_string_lossy());
+                e.context(message)
+            }),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        let mut path = dirs::config_dir().unwrap_or_default();
+        path.push(format!("{}/config.toml", crate_name!()));
+        path
+    }
+
+    /// Database file, falling back to the user data directory
+    pub fn db_path(&self) -> PathBuf {
+        match &self.db {
+            Some(path) => expand_home(path),
+            None => {
+                let mut path = dirs::data_dir().expect("failed to get user data directory");
+                path.push(format!("{}/db.txt", crate_name!()));
+                path
+            }
+        }
+    }
+
+    /// Database file of a profile
+    pub fn profile_db(&self, name: &str) -> Result<PathBuf> {
+        if name == DEFAULT_PROFILE {
+            return Ok(self.db_path());
+        }
+        match self.profiles.get(name) {
+            Some(profile) => Ok(expand_home(&profile.db)),
+            None => Err(anyhow!("unknown profile: {name}")),
+        }
+    }
+
+    /// Names and database files of all profiles, starting with the default one
+    pub fn profiles(&self) -> Vec<(String, PathBuf)> {
+        let mut result = vec![(DEFAULT_PROFILE.to_string(), self.db_path())];
+        for (name, profile) in &self.profiles {
+            if name != DEFAULT_PROFILE {
+                result.push((name.clone(), expand_home(&profile.db)));
+            }
+        }
+        result
+    }
+}
+
+/// Set the config returned by `get`. Can only be done once.
+pub fn set(config: Config) {
+    CONFIG.set(config).ok();
+}
+
+/// Get the current config, or the default config if none was set
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+// "~/foo" -> "/home/user/foo"
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub fn command() -> Command {
+    Command::new("config")
+        .about("Show or edit configuration")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(Command::new("show").about("Print the current configuration"))
+        .subcommand(Command::new("path").about("Print the path of the configuration file"))
+        .subcommand(
+            Command::new("edit").about("Edit the configuration file with the default editor"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("show", _)) => print!("{}", toml::to_string_pretty(get())?),
+        Some(("path", _)) => println!("{}", Config::path().to_string_lossy()),
+        Some(("edit", _)) => edit()?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn edit() -> Result<()> {
+    let path = Config::path();
+    let original = fs::read_to_string(&path).unwrap_or_else(|_| TEMPLATE.into());
+
+    let new = edit::edit(&original)?;
+    if new == original && path.exists() {
+        println!("No changes.");
+        return Ok(());
+    }
+
+    if let Err(e) = Config::parse(&new) {
+        return Err(anyhow!("{e}\n\nYour input:\n{new}\n\nNo changes saved."));
+    }
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, new)?;
+    println!("Configuration updated.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_template() {
+        let config = Config::parse(TEMPLATE).unwrap();
+        assert_eq!(config.watchlist_tag, "watchlist");
+        assert!(config.tui.confirm_delete);
+    }
+
+    #[test]
+    fn parses() {
+        let config = Config::parse(
+            "db = \"/tmp/db.txt\"
+watchlist_tag = \"todo\"
+rating_scale = 5
+
+[ls]
+tags = true",
+        )
+        .unwrap();
+        assert_eq!(config.db_path(), PathBuf::from("/tmp/db.txt"));
+        assert_eq!(config.watchlist_tag, "todo");
+        assert_eq!(config.rating_scale, Some(5));
+        assert!(config.ls.tags);
+        assert!(!config.ls.note);
+        assert!(config.tui.show_tags);
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let config = Config::parse(
+            "db = \"/tmp/db.txt\"
+
+[profiles.kids]
+db = \"/tmp/kids.txt\"",
+        )
+        .unwrap();
+        assert_eq!(config.profile_db("kids").unwrap(), PathBuf::from("/tmp/kids.txt"));
+        assert_eq!(config.profile_db("default").unwrap(), PathBuf::from("/tmp/db.txt"));
+        assert!(config.profile_db("foo").is_err());
+        assert_eq!(
+            config.profiles(),
+            vec![
+                ("default".into(), PathBuf::from("/tmp/db.txt")),
+                ("kids".into(), PathBuf::from("/tmp/kids.txt"))
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let error = Config::parse("colour = true").unwrap_err();
+        assert!(error.to_string().starts_with("invalid config"));
+    }
+
+    #[test]
+    fn rejects_invalid_date_format() {
+        assert!(Config::parse("date_format = \"%d.%m.%Y\"").is_ok());
+        let error = Config::parse("date_format = \"%Q\"").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Invalid { key, .. }) if key == "date_format"
+        ));
+    }
+
+    #[test]
+    fn expands_home() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home(Path::new("~/db.txt")), home.join("db.txt"));
+        assert_eq!(expand_home(Path::new("/db.txt")), PathBuf::from("/db.txt"));
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    arg_util, args,
+    media::{
+        repo::Repo,
+        storage::{self, Kind},
+    },
+};
+
+pub fn command() -> Command {
+    Command::new("convert")
+        .about("Convert a database to another format, e.g. db.txt to db.json")
+        .long_about(format!(
+            "Convert a database to another format, e.g. db.txt to db.json
+
+The format is chosen by the file extension: .json, .yaml or .yml, .toml, .enc and
+.sqlite. Other files are text files. Use --to for other kinds, e.g. a directory.
+With a single path, the current database is converted to it. Without one, it is
+converted to a file next to it, e.g. 'convert --to sqlite' writes db.sqlite.
+
+{}
+
+The database that was converted is left as it is.",
+            args::KIND_HELP
+        ))
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("PATHS")
+                .required(false)
+                .num_args(1..=2)
+                .value_names(["IN", "OUT"])
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Database to convert [default: the current one] and path of the new one"),
+        )
+        .arg(args::kind().help("Kind of storage to convert to [default: by extension of OUT]"))
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let paths: Vec<&PathBuf> = matches.get_many("PATHS").into_iter().flatten().collect();
+    let kind = matches.get_one::<String>("TO").map(|name| Kind::from_name(name).unwrap());
+
+    let (input, output) = match (paths.as_slice(), kind) {
+        ([input, output], _) => ((*input).clone(), (*output).clone()),
+        ([output], _) => (arg_util::db_from_matches(matches)?, (*output).clone()),
+        ([], Some(kind)) => {
+            let input = arg_util::db_from_matches(matches)?;
+            let output = input.with_extension(kind.extension());
+            (input, output)
+        }
+        _ => return Err(anyhow!("Give the path of the new database or its kind with --to")),
+    };
+    let kind = kind.unwrap_or_else(|| Kind::detect(&output));
+
+    let repo = arg_util::open_repo(&input, arg_util::lenient_from_matches(matches))?;
+    copy(&repo, &output, kind)?;
+
+    println!("Converted {} item(s) to: {}", repo.len(), output.to_string_lossy());
+    if !repo.errors().is_empty() {
+        println!("{} invalid entries were skipped.", repo.errors().len());
+    }
+    if paths.len() < 2 {
+        println!("To use it, set db = \"{}\" in the config.", output.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Write all items of a repo to a new database
+pub fn copy(repo: &Repo, target: &Path, kind: Kind) -> Result<()> {
+    if target.exists() {
+        return Err(anyhow!("already exists: {}", target.to_string_lossy()));
+    }
+
+    let items: Vec<_> = repo.get_all().into_iter().cloned().collect();
+    storage::open(target, kind)?.save(&items)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn converts_without_loss() {
+        let dir = std::env::temp_dir().join("mtracker_test_converts_without_loss");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+
+        let text = "Forrest Gump
+year: 1994
+rating: 9
+tags: drama, comedy/romantic
+note: recommended by Max: \"see it\"
+last_seen: 2020-12-31
+
+Whiplash
+
+Bodies Bodies Bodies
+year: 2022
+tags: watchlist";
+        let path = dir.join("db.txt");
+        fs::write(&path, text).unwrap();
+
+        // Through every format and back
+        let mut kinds = vec![Kind::Json, Kind::Yaml, Kind::Toml];
+        #[cfg(feature = "sqlite")]
+        kinds.push(Kind::Sqlite);
+        kinds.push(Kind::Text);
+
+        let mut path = path;
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let repo = Repo::new(&path).unwrap();
+            let target = dir.join(format!("db{i}")).with_extension(kind.extension());
+            copy(&repo, &target, kind).unwrap();
+            assert_eq!(Repo::new(&target).unwrap().get_all(), repo.get_all(), "{kind:?}");
+            path = target;
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        // Never overwrites
+        assert!(copy(&Repo::new(&path).unwrap(), &path, Kind::Text).is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
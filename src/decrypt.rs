@@ -0,0 +1,29 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use clap::{ArgMatches, Command};
+
+use crate::{
+    arg_util,
+    media::storage::{encrypted, Kind},
+};
+
+pub fn command() -> Command {
+    Command::new("decrypt")
+        .about("Decrypt the database, turning it into a plain text file")
+        .arg_required_else_help(false)
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let path = arg_util::db_from_matches(matches)?;
+    if Kind::detect(&path) != Kind::Encrypted {
+        return Err(anyhow!("not encrypted: {}", path.to_string_lossy()));
+    }
+
+    let passphrase = encrypted::passphrase(&path, false)?;
+    let (content, _) = encrypted::Secret::decrypt(&path, &fs::read(&path)?, &passphrase)?;
+    encrypted::write(&path, content.as_bytes())?;
+
+    println!("Decrypted: {}", path.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    arg_util, config,
+    error::InvalidEntry,
+    media::{repo::Repo, storage::Kind, tag, Media},
+};
+
+pub fn command() -> Command {
+    Command::new("doctor")
+        .about("Check the database for duplicates and other problems")
+        .arg_required_else_help(false)
+        .arg(
+            Arg::new("FIX")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("fix")
+                .help("Repair tag spellings, rated items on the watchlist and trailing whitespace"),
+        )
+}
+
+/// A problem found in the database. Items are referred to by index.
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    /// Entry that failed to parse, with the location of the error
+    Invalid(InvalidEntry),
+    /// Items with the same name (ignoring case and whitespace) and year, or
+    /// where one of them has no year
+    Duplicates(Vec<usize>),
+    /// Items with the same ID, of which only the first one can be found by it
+    SameId(Vec<usize>),
+    MissingYear(usize),
+    /// Spellings of the same tag, the preferred one first
+    TagSpellings(Vec<String>),
+    RatingAboveScale(usize),
+    RatedOnWatchlist(usize),
+    /// Line number in the database file
+    TrailingWhitespace(usize),
+}
+
+impl Issue {
+    fn describe(&self, repo: &Repo) -> String {
+        let handle = |i: &usize| repo.get_by_index(*i).handle().to_string();
+        match self {
+            Issue::Duplicates(items) => {
+                let handles: Vec<String> = items.iter().map(handle).collect();
+                format!("Possible duplicates: {}", handles.join(", "))
+            }
+            Issue::SameId(items) => {
+                let handles: Vec<String> = items.iter().map(handle).collect();
+                let id = repo.get_by_index(items[0]).id.as_deref().unwrap_or_default();
+                format!("Same ID #{id}: {}", handles.join(", "))
+            }
+            Issue::Invalid(error) => format!("Invalid entry: {error}"),
+            Issue::MissingYear(i) => format!("Missing year: {}", handle(i)),
+            Issue::TagSpellings(tags) => format!("Inconsistent tag spellings: {}", tags.join(", ")),
+            Issue::RatingAboveScale(i) => format!(
+                "Rating above scale of {}: {} ({})",
+                repo.max_rating(),
+                handle(i),
+                repo.get_by_index(*i).rating.unwrap_or(0)
+            ),
+            Issue::RatedOnWatchlist(i) => format!("Rated but on watchlist: {}", handle(i)),
+            Issue::TrailingWhitespace(line) => format!("Trailing whitespace in line {line}"),
+        }
+    }
+
+    fn fixable(&self) -> bool {
+        matches!(
+            self,
+            Issue::TagSpellings(_) | Issue::RatedOnWatchlist(_) | Issue::TrailingWhitespace(_)
+        )
+    }
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    // Invalid entries are reported like any other problem
+    let mut repo = Repo::open(&arg_util::db_from_matches(matches)?, true)?;
+    let fix = *matches.get_one::<bool>("FIX").unwrap_or(&false);
+
+    // Whitespace only matters in text files, other formats are checked when loading
+    let file = match repo.kind() {
+        Kind::Text => std::fs::read_to_string(&repo.path).unwrap_or_default(),
+        _ => String::new(),
+    };
+    let issues = check(&repo, &file);
+
+    for issue in &issues {
+        let fixed = if fix && issue.fixable() {
+            " (fixed)"
+        } else {
+            ""
+        };
+        println!("{}{fixed}", issue.describe(&repo));
+    }
+
+    if issues.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    println!("{} problem(s) found", issues.len());
+
+    if fix {
+        for issue in &issues {
+            apply_fix(&mut repo, issue);
+        }
+        // Formatting isn't part of the items, so they are all written again
+        match issues.iter().any(|i| matches!(i, Issue::TrailingWhitespace(_))) {
+            true => repo.rewrite()?,
+            false => repo.write()?,
+        }
+    } else if issues.iter().any(Issue::fixable) {
+        println!("Run 'mtracker doctor --fix' to repair some of them.");
+    }
+
+    Ok(())
+}
+
+/// Find problems in the items of a repo and the content of its file
+pub fn check(repo: &Repo, file: &str) -> Vec<Issue> {
+    let items = repo.get_all();
+    let mut issues: Vec<Issue> = repo.errors().iter().cloned().map(Issue::Invalid).collect();
+    issues.extend(duplicates(&items));
+    issues.extend(same_ids(&items));
+
+    issues.extend((0..items.len()).filter(|&i| items[i].year.is_none()).map(Issue::MissingYear));
+
+    issues.extend(tag_spellings(&items).into_iter().map(Issue::TagSpellings));
+
+    if let Some(scale) = config::get().rating_scale {
+        issues.extend(
+            (0..items.len())
+                .filter(|&i| items[i].rating.is_some_and(|r| r > scale))
+                .map(Issue::RatingAboveScale),
+        );
+    }
+
+    issues.extend(
+        (0..items.len())
+            .filter(|&i| items[i].rating.is_some() && items[i].on_watchlist())
+            .map(Issue::RatedOnWatchlist),
+    );
+
+    issues.extend(
+        file.lines()
+            .enumerate()
+            .filter(|(_, l)| l.trim_end() != *l)
+            .map(|(i, _)| Issue::TrailingWhitespace(i + 1)),
+    );
+
+    issues
+}
+
+fn apply_fix(repo: &mut Repo, issue: &Issue) {
+    match issue {
+        Issue::TagSpellings(tags) => {
+            for i in 0..repo.len() {
+                respell(repo.get_by_index_mut(i), &tags[0], &tags[1..]);
+            }
+        }
+        Issue::RatedOnWatchlist(i) => {
+            repo.get_by_index_mut(*i).remove_tag(&config::get().watchlist_tag).ok();
+        }
+        // Values are trimmed when parsing, names are not
+        Issue::TrailingWhitespace(_) => {
+            for i in 0..repo.len() {
+                let item = repo.get_by_index_mut(i);
+                item.name = item.name.trim().to_string();
+            }
+        }
+        _ => {}
+    }
+}
+
+// "Alien  3" -> "alien 3"
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn duplicates(items: &[&Media]) -> Vec<Issue> {
+    let mut by_name: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, item) in items.iter().enumerate() {
+        by_name.entry(normalize_name(&item.name)).or_default().push(i);
+    }
+
+    let mut issues = vec![];
+    for group in by_name.into_values().filter(|g| g.len() > 1) {
+        // An item without year may be a duplicate of any other item
+        if group.iter().any(|&i| items[i].year.is_none()) {
+            issues.push(Issue::Duplicates(group));
+            continue;
+        }
+
+        // Otherwise only items of the same year are, e.g. not remakes
+        let mut by_year: BTreeMap<Option<u16>, Vec<usize>> = BTreeMap::new();
+        for i in group {
+            by_year.entry(items[i].year).or_default().push(i);
+        }
+        issues.extend(by_year.into_values().filter(|g| g.len() > 1).map(Issue::Duplicates));
+    }
+    issues
+}
+
+fn same_ids(items: &[&Media]) -> Vec<Issue> {
+    let mut by_id: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, item) in items.iter().enumerate() {
+        if let Some(id) = &item.id {
+            by_id.entry(id).or_default().push(i);
+        }
+    }
+    by_id.into_values().filter(|g| g.len() > 1).map(Issue::SameId).collect()
+}
+
+// "Sci_Fi" -> "scifi"
+fn normalize_tag(tag: &str) -> String {
+    tag.chars()
+        .filter(|c| !matches!(c, '-' | '_' | ' '))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Groups of tags that are spelled differently but mean the same, starting
+/// with the canonical form (see `tag::normalize`) or the most used spelling
+fn tag_spellings(items: &[&Media]) -> Vec<Vec<String>> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for t in items.iter().flat_map(|i| &i.tags) {
+        *counts.entry(t.as_str()).or_insert(0) += 1;
+    }
+
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for &t in counts.keys() {
+        groups.entry(normalize_tag(tag::normalize(t))).or_default().push(t);
+    }
+
+    groups
+        .into_values()
+        .filter(|g| g.len() > 1 || g.iter().any(|t| tag::normalize(t) != *t))
+        .map(|mut g| {
+            g.sort_by(|a, b| {
+                let canonical = |t: &str| tag::normalize(t) == t;
+                canonical(b)
+                    .cmp(&canonical(a))
+                    .then_with(|| counts[b].cmp(&counts[a]))
+                    .then_with(|| a.cmp(b))
+            });
+
+            // Aliases are replaced by their canonical form, even if unused
+            let canonical = tag::normalize(g[0]);
+            if canonical != g[0] {
+                g.insert(0, canonical);
+            }
+            g.into_iter().map(String::from).collect()
+        })
+        .collect()
+}
+
+// Replace other spellings of a tag, without adding it twice
+fn respell(item: &mut Media, tag: &str, others: &[String]) {
+    let len = item.tags.len();
+    item.tags.retain(|t| !others.contains(t));
+    if item.tags.len() != len && !item.tags.iter().any(|t| t == tag) {
+        item.tags.push(tag.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(name: &str, content: &str) -> Repo {
+        let mut path = std::env::temp_dir();
+        path.push(name);
+        std::fs::write(&path, content).unwrap();
+        let repo = Repo::new(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        repo
+    }
+
+    #[test]
+    fn finds_duplicates() {
+        let repo = repo(
+            "mtracker_test_finds_duplicates.txt",
+            "Alien
+year: 1979
+
+alien
+year: 1979
+
+Halloween
+year: 1978
+
+Halloween
+year: 2018
+
+Pearl
+
+pearl
+year: 2022",
+        );
+        assert_eq!(
+            duplicates(&repo.get_all()),
+            vec![Issue::Duplicates(vec![0, 1]), Issue::Duplicates(vec![4, 5])]
+        );
+    }
+
+    #[test]
+    fn finds_same_ids() {
+        let repo = repo(
+            "mtracker_test_finds_same_ids.txt",
+            "Alien
+id: a1
+
+Aliens
+id: a1
+
+Pearl
+id: p1
+
+Dune",
+        );
+        assert_eq!(same_ids(&repo.get_all()), vec![Issue::SameId(vec![0, 1])]);
+    }
+
+    #[test]
+    fn finds_tag_spellings() {
+        let repo = repo(
+            "mtracker_test_finds_tag_spellings.txt",
+            "Alien
+tags: sci-fi, horror
+
+Aliens
+tags: Sci-Fi, horror
+
+Dune
+tags: SciFi, sci-fi",
+        );
+        assert_eq!(tag_spellings(&repo.get_all()), vec![vec!["sci-fi", "Sci-Fi", "SciFi"]]);
+    }
+
+    #[test]
+    fn checks() {
+        let repo = repo(
+            "mtracker_test_checks.txt",
+            "Pearl
+year: 2022
+rating: 8
+tags: watchlist",
+        );
+        let issues = check(&repo, "Pearl \nyear: 2022");
+        assert_eq!(issues, vec![Issue::RatedOnWatchlist(0), Issue::TrailingWhitespace(1)]);
+    }
+
+    #[test]
+    fn respells() {
+        let mut item = Media::new("Dune", None);
+        item.tags = vec!["SciFi".into(), "sci-fi".into(), "drama".into()];
+        respell(&mut item, "sci-fi", &["SciFi".into()]);
+        assert_eq!(item.tags, vec!["sci-fi", "drama"]);
+
+        item.tags = vec!["SciFi".into()];
+        respell(&mut item, "sci-fi", &["SciFi".into()]);
+        assert_eq!(item.tags, vec!["sci-fi"]);
+    }
+}
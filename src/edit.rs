@@ -1,9 +1,12 @@
-use anyhow::{anyhow, Result};
+use std::{fs, io::Write, path::Path};
+
+use anyhow::Result;
 use clap::{ArgMatches, Command};
 
 use crate::{
     arg_util, args,
-    media::{handle, repo, Media},
+    error::Error,
+    media::{handle, parser, repo, schema, storage::Kind, Media},
 };
 
 pub fn command() -> Command {
@@ -19,34 +22,35 @@ pub fn handle(matches: &ArgMatches) -> Result<()> {
     let mut repo = arg_util::repo_from_matches(matches)?;
 
     if let Some(handle) = arg_util::handle_from_matches(matches)? {
+        let handle = arg_util::resolve(&repo, &handle, false)?.unwrap();
         edit_db_entry(&mut repo, &handle)
     } else {
         edit_db(&mut repo)
     }
 }
 
+/// Marks comments that are added to the text when editing fails validation
+const MARKER: &str = "# mtracker:";
+
 fn edit_db_entry(repo: &mut repo::Repo, handle: &handle::Handle) -> Result<()> {
     // Find media
     let Some(item) = repo.get(handle) else {
-        return Err(anyhow!("item not found: {handle}"));
+        return Err(Error::NotFound(handle.clone()).into());
     };
+    let db_entry = item.to_db_entry();
 
     // Edit with editor
-    let db_entry = edit::edit(item.to_db_entry())?;
-
-    // Create new item based on db entry
-    let new_item = match Media::from_db_entry(&db_entry) {
-        Ok(item) => item,
-        Err(e) => {
-            return Err(anyhow!(
-                "failed to edit {handle}: {e}\n\nYour input:\n{db_entry}\n\nNo changes made."
-            ))
-        }
+    let Some((new_db_entry, new_item)) = edit_entry(&db_entry)? else {
+        println!("Aborted, no changes made.");
+        return Ok(());
     };
+    if new_db_entry == db_entry {
+        println!("No changes.");
+        return Ok(());
+    }
 
     // Replace old item with new item
-    repo.remove_by_handle(handle)?;
-    repo.add(new_item)?;
+    repo.replace(handle, new_item)?;
     repo.write()?;
 
     println!("Updated item: {handle}");
@@ -54,11 +58,39 @@ fn edit_db_entry(repo: &mut repo::Repo, handle: &handle::Handle) -> Result<()> {
 }
 
 fn edit_db(repo: &mut repo::Repo) -> Result<()> {
-    // Get original db
-    let original_db = std::fs::read_to_string(&repo.path).unwrap_or_default();
+    // Get original db. Items of other storage are edited as if they were in a text file.
+    let original_db = if repo.kind() == Kind::Text {
+        std::fs::read_to_string(&repo.path).unwrap_or_default()
+    } else {
+        let entries: Vec<String> = repo.get_all().iter().map(|m| m.to_db_entry()).collect();
+        entries.join("\n\n")
+    };
 
-    // Edit db
-    let new_db = edit::edit(&original_db)?;
+    // Edit db until it is valid
+    let validate = |text: &str| -> Result<Vec<Media>, Vec<parser::ParseError>> {
+        let mut items = vec![];
+        let mut errors: Vec<_> = schema::read_header(text).err().into_iter().collect();
+        for block in parser::blocks(text) {
+            // Included files are not edited, only the includes
+            match block.includes() {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => errors.push(e),
+                None => match block.parse() {
+                    Ok(item) => items.push(item),
+                    Err(e) => errors.push(e),
+                },
+            }
+        }
+        if errors.is_empty() {
+            Ok(items)
+        } else {
+            Err(errors)
+        }
+    };
+    let Some((new_db, items)) = edit_until_valid(&original_db, validate)? else {
+        println!("Aborted, no changes saved.");
+        return Ok(());
+    };
 
     // No changes, abort
     if new_db == original_db {
@@ -66,19 +98,126 @@ fn edit_db(repo: &mut repo::Repo) -> Result<()> {
         return Ok(());
     }
 
-    // Validate
-    let blocks: Vec<&str> = new_db.split("\n\n").filter(|b| !b.is_empty()).map(str::trim).collect();
-    for (i, block) in blocks.iter().enumerate() {
-        if let Err(e) = Media::from_db_entry(block) {
-            return Err(anyhow!(
-                "validation failed at entry {}: {e}\n\n{block}\n\nNo changes saved.",
-                i + 1
-            ));
-        }
-    }
-
     // Save changes
-    std::fs::write(&repo.path, new_db)?;
+    if repo.kind() == Kind::Text {
+        std::fs::write(&repo.path, new_db)?;
+    } else {
+        repo.set_all(items);
+        repo.write()?;
+    }
     println!("Database updated.");
     Ok(())
 }
+
+/// Edit a single database entry, see `edit_until_valid`
+pub fn edit_entry(db_entry: &str) -> Result<Option<(String, Media)>> {
+    edit_until_valid(db_entry, |text| parser::parse_single(text).map_err(|e| vec![e]))
+}
+
+/// Open text in the editor until `parse` accepts it. On errors, the editor is
+/// opened again with the errors marked as comments. Returns `None` if the user
+/// aborts by deleting everything or not changing anything after an error.
+pub fn edit_until_valid<T>(
+    text: &str,
+    parse: impl Fn(&str) -> Result<T, Vec<parser::ParseError>>,
+) -> Result<Option<(String, T)>> {
+    let mut input = text.to_string();
+    let mut failed: Option<String> = None;
+
+    loop {
+        let output = strip_markers(&edit_text(&input)?);
+
+        if let Some(previous) = &failed {
+            if output.trim().is_empty() || output.trim_end() == previous.trim_end() {
+                return Ok(None);
+            }
+        }
+
+        match parse(&output) {
+            Ok(value) => return Ok(Some((output, value))),
+            Err(errors) => {
+                input = mark_errors(&output, &errors);
+                failed = Some(output);
+            }
+        }
+    }
+}
+
+// Edit text in a temporary file that only the user can read. It is overwritten
+// before it is deleted, as it may contain an encrypted database.
+fn edit_text(text: &str) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("mtracker-{:016x}.txt", fastrand::u64(..)));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(text.as_bytes())?;
+
+    let result = edit::edit_file(&path).and_then(|()| fs::read_to_string(&path));
+    wipe(&path);
+    Ok(result?)
+}
+
+fn wipe(path: &Path) {
+    if let Ok(mut file) = fs::OpenOptions::new().write(true).open(path) {
+        let len = file.metadata().map_or(0, |m| m.len());
+        file.write_all(&vec![0; len as usize]).ok();
+        file.sync_all().ok();
+    }
+    fs::remove_file(path).ok();
+}
+
+// Insert a comment above each line with an error
+fn mark_errors(text: &str, errors: &[parser::ParseError]) -> String {
+    let mut result = format!(
+        "{MARKER} Please fix the errors below. Delete everything or save without changes to abort.\n"
+    );
+    for (i, line) in text.lines().enumerate() {
+        for e in errors.iter().filter(|e| e.line == i + 1) {
+            result += &format!("{MARKER} column {}: {e}\n", e.column);
+        }
+        result += line;
+        result += "\n";
+    }
+    result
+}
+
+fn strip_markers(text: &str) -> String {
+    let mut result: String =
+        text.split_inclusive('\n').filter(|line| !line.starts_with(MARKER)).collect();
+
+    // Keep the text as it was, e.g. without a trailing newline added by the editor
+    if !text.ends_with('\n') && result.ends_with('\n') {
+        result.pop();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_errors() {
+        let text = "Alien
+year: 19x9
+
+Aliens
+foo: bar";
+        let errors: Vec<_> = parser::blocks(text).iter().filter_map(|b| b.parse().err()).collect();
+        let marked = mark_errors(text, &errors);
+        assert_eq!(
+            marked.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "Alien",
+                "# mtracker: column 7: failed to parse year: invalid digit found in string",
+                "year: 19x9",
+                "",
+                "Aliens",
+                "# mtracker: column 1: unknown key: foo",
+                "foo: bar"
+            ]
+        );
+        assert_eq!(strip_markers(&marked), format!("{text}\n"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use clap::{ArgMatches, Command};
+use zeroize::Zeroizing;
+
+use crate::{
+    arg_util,
+    media::{
+        parser,
+        storage::{encrypted, Kind},
+    },
+};
+
+pub fn command() -> Command {
+    Command::new("encrypt")
+        .about("Encrypt the database with a passphrase")
+        .long_about(
+            "Encrypt the database with a passphrase
+
+The database is then only decrypted in memory. Other commands ask for the
+passphrase, or read it from MTRACKER_PASSPHRASE. Only text databases without
+includes can be encrypted.",
+        )
+        .arg_required_else_help(false)
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let path = arg_util::db_from_matches(matches)?;
+
+    // Make sure the database can be read once encrypted
+    let repo = arg_util::open_repo(&path, arg_util::lenient_from_matches(matches))?;
+    match repo.kind() {
+        Kind::Text => {}
+        Kind::Encrypted => return Err(anyhow!("already encrypted: {}", path.to_string_lossy())),
+        _ => {
+            return Err(anyhow!(
+                "only text databases can be encrypted, use 'mtracker convert' first"
+            ))
+        }
+    }
+
+    // Also includes that match no files, as they can't be read once encrypted
+    let content = Zeroizing::new(fs::read_to_string(&path).unwrap_or_default());
+    if has_includes(&content) {
+        return Err(anyhow!("databases with includes can't be encrypted"));
+    }
+
+    let passphrase = encrypted::passphrase(&path, true)?;
+    let secret = encrypted::Secret::new(&passphrase)?;
+    encrypted::write(&path, &secret.encrypt(&content)?)?;
+
+    println!("Encrypted: {}", path.to_string_lossy());
+    Ok(())
+}
+
+// Whether the content of a text database has include blocks
+fn has_includes(content: &str) -> bool {
+    parser::blocks(content).iter().any(|b| b.includes().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_includes() {
+        assert!(!has_includes("Alien\nyear: 1979\n\nPearl"));
+        assert!(has_includes("Alien\n\ninclude: books/*.txt"));
+
+        // Even if the include matches no files
+        let dir = std::env::temp_dir().join("mtracker_test_finds_includes");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let content = "include: books/*.txt\n\nAlien";
+        fs::write(dir.join("db.txt"), content).unwrap();
+        let repo = crate::Repo::new(&dir.join("db.txt")).unwrap();
+        assert!(repo.get_all().iter().all(|m| m.origin.is_none()));
+        assert!(has_includes(content));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fmt, io, path::PathBuf};
+
+use crate::media::{handle::Handle, parser::ParseError, schema};
+
+/// Errors of the library API
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("item not found: {0}")]
+    NotFound(Handle),
+
+    /// A handle the user entered matches several items
+    #[error("\"{handle}\" matches several items:{}", list(.candidates))]
+    Ambiguous {
+        handle: Handle,
+        candidates: Vec<Handle>,
+    },
+
+    #[error("item already exists: {0}")]
+    AlreadyExists(Handle),
+
+    #[error("tag not found: {0}")]
+    TagNotFound(String),
+
+    /// Invalid input for a field, e.g. a note with several lines
+    #[error("{message}")]
+    Invalid { key: String, message: String },
+
+    /// A single entry couldn't be parsed
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    /// Entries of the database couldn't be read
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    InvalidEntries(Vec<InvalidEntry>),
+
+    /// A JSON, YAML or TOML database couldn't be parsed, or has invalid items,
+    /// or an encrypted one couldn't be decrypted
+    #[error("{}: {message}", path.to_string_lossy())]
+    Corrupt { path: PathBuf, message: String },
+
+    /// The database was written by a newer version of mtracker
+    #[error(
+        "{} has format version {version}, but this version of mtracker only supports up to {}. \
+         Please update mtracker.",
+        path.to_string_lossy(),
+        schema::VERSION
+    )]
+    NewerFormat { path: PathBuf, version: u32 },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// Reading or writing the database failed
+    #[error(transparent)]
+    Storage(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    pub(crate) fn storage(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        match e.downcast::<io::Error>() {
+            Ok(e) => Error::Io(e),
+            Err(e) => Error::Storage(e.into()),
+        }
+    }
+
+    pub(crate) fn invalid(key: &str, message: impl fmt::Display) -> Self {
+        Error::Invalid {
+            key: key.into(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Exit code of the command line interface, so that scripts can tell errors
+    /// apart. Other errors exit with 1, invalid arguments with 2.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotFound(_) | Error::TagNotFound(_) => 3,
+            Error::Ambiguous { .. } => 4,
+            Error::AlreadyExists(_) => 5,
+            Error::Invalid { .. } => 6,
+            Error::Parse(_)
+            | Error::InvalidEntries(_)
+            | Error::Corrupt { .. }
+            | Error::NewerFormat { .. } => 7,
+            Error::Io(_) | Error::Storage(_) => 8,
+        }
+    }
+}
+
+// "\n  Alien (1979)\n  Aliens (1986)"
+fn list(handles: &[Handle]) -> String {
+    handles.iter().map(|h| format!("\n  {h}")).collect()
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An entry of the database that couldn't be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidEntry {
+    /// The file containing the entry
+    pub path: PathBuf,
+    pub error: ParseError,
+}
+
+/// "db.txt:12:7: failed to parse year"
+impl fmt::Display for InvalidEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let e = &self.error;
+        write!(f, "{}:{}:{}: {e}", self.path.to_string_lossy(), e.line, e.column)
+    }
+}
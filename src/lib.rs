@@ -1,23 +1,149 @@
-use anyhow::Result;
-use clap::{crate_authors, crate_name, crate_version, Command};
+//! mtracker keeps track of watched movies and series in a human-readable text
+//! file. Besides the command line interface, the database can be used as a
+//! library:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use mtracker::{ListOptions, Query, Repo};
+//!
+//! let mut repo = Repo::new(Path::new("db.txt"))?;
+//! for item in Query::parse("horror 2020- !watchlist").filter(&repo) {
+//!     println!("{}", item.as_line(&ListOptions::default()));
+//! }
+//!
+//! let handle = mtracker::Handle::from_user_input("Pearl (2022)");
+//! repo.update(&handle, |item| item.rating = Some(8))?;
+//! repo.write()?;
+//! # Ok::<(), mtracker::Error>(())
+//! ```
+//!
+//! Tag aliases and the config of the command line interface aren't loaded, so
+//! the watchlist tag is "watchlist".
+//!
+//! `Repo` reads all items. To read or change single items without that, e.g.
+//! in a large SQLite database, use the storage directly:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use mtracker::{Handle, Kind};
+//!
+//! let path = Path::new("db.sqlite");
+//! let mut storage = mtracker::open_storage(path, Kind::detect(path))?;
+//! if let Some(mut item) = storage.get(&Handle::from_user_input("Pearl (2022)"))? {
+//!     item.rating = Some(8);
+//!     storage.upsert(item)?;
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use clap::{
+    crate_authors, crate_name, crate_version, error::ErrorKind, parser::ValueSource, ArgMatches,
+    Command,
+};
+use clap_complete::CompleteEnv;
 
 mod add;
 mod arg_util;
 mod args;
+mod batch;
+mod completions;
+mod config;
+mod convert;
+mod decrypt;
+mod doctor;
 mod edit;
+mod encrypt;
+mod error;
 mod list;
+mod manpage;
 mod media;
+mod migrate;
+mod pick;
+mod query;
 mod rate;
 mod remove;
+mod rename;
+mod stats;
 mod tags;
 mod tui;
 mod unrate;
 
+pub use error::{Error, InvalidEntry, Result};
+pub use media::{
+    format::ListOptions,
+    handle::Handle,
+    parser::{ParseError, ParseErrorKind},
+    repo::{Lookup, Repo},
+    storage::{encrypted::set_passphrase, open as open_storage, Kind, Loaded, Storage},
+    Media,
+};
+pub use query::Query;
+
 #[allow(clippy::missing_errors_doc)]
 #[allow(clippy::missing_panics_doc)]
-pub fn run() -> Result<()> {
-    // Setup commands
-    let matches = Command::new(crate_name!())
+pub fn run() -> anyhow::Result<()> {
+    // Print completions if requested by the shell (COMPLETE=<shell> mtracker)
+    CompleteEnv::with_factory(command).complete();
+
+    let matches = command().get_matches();
+    check_profile(&matches);
+
+    // Load config. A broken config must not prevent fixing it with 'config edit'.
+    match config::Config::load() {
+        Ok(c) => config::set(c),
+        Err(e) if matches.subcommand_name() == Some("config") => eprintln!("{e}"),
+        Err(e) => return Err(e),
+    }
+
+    // Load tag aliases
+    media::tag::set_aliases(media::tag::Aliases::load()?);
+
+    // Run command
+    match matches.subcommand() {
+        Some(("ls", matches)) => list::handle(matches),
+        Some(("add", matches)) => add::handle(matches),
+        Some(("rm", matches)) => remove::handle(matches),
+        Some(("rate", matches)) => rate::handle(matches),
+        Some(("unrate", matches)) => unrate::handle(matches),
+        Some(("edit", matches)) => edit::handle(matches),
+        Some(("mv", matches)) => rename::handle(matches),
+        Some(("tags", matches)) => tags::handle(matches),
+        Some(("stats", matches)) => stats::handle(matches),
+        Some(("doctor", matches)) => doctor::handle(matches),
+        Some(("convert", matches)) => convert::handle(matches),
+        Some(("migrate", matches)) => migrate::handle(matches),
+        Some(("encrypt", matches)) => encrypt::handle(matches),
+        Some(("decrypt", matches)) => decrypt::handle(matches),
+        Some(("pick", matches)) => pick::handle(matches),
+        Some(("config", matches)) => config::handle(matches),
+        Some(("completions", matches)) => completions::handle(matches),
+        Some(("manpage", matches)) => manpage::handle(matches, command()),
+        Some(("batch", matches)) => batch::handle(matches, command()),
+        None => tui::run(&matches),
+        _ => unreachable!(),
+    }
+}
+
+// A profile from MTRACKER_PROFILE is overridden by --db and --all-profiles, but
+// one given as option conflicts with them
+fn check_profile(matches: &ArgMatches) {
+    let matches = matches.subcommand().map_or(matches, |(_, m)| m);
+    if matches.value_source("PROFILE") != Some(ValueSource::CommandLine) {
+        return;
+    }
+    let other = match matches.try_get_one::<bool>("ALL_PROFILES") {
+        Ok(Some(true)) => "--all-profiles",
+        _ if matches.contains_id("DB") => "--db <DB>",
+        _ => return,
+    };
+    let message = format!("the argument '--profile <PROFILE>' cannot be used with '{other}'");
+    command().error(ErrorKind::ArgumentConflict, message).exit();
+}
+
+fn command() -> Command {
+    Command::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(format!("{} - cli media tracker", crate_name!()))
@@ -25,25 +151,25 @@ pub fn run() -> Result<()> {
         .arg_required_else_help(false)
         .allow_external_subcommands(false)
         .arg(args::db())
+        .arg(args::profile())
+        .arg(args::lenient())
         .subcommand(list::command())
         .subcommand(add::command())
         .subcommand(remove::command())
         .subcommand(rate::command())
         .subcommand(unrate::command())
         .subcommand(edit::command())
+        .subcommand(rename::command())
         .subcommand(tags::command())
-        .get_matches();
-
-    // Run command
-    match matches.subcommand() {
-        Some(("ls", matches)) => list::handle(matches),
-        Some(("add", matches)) => add::handle(matches),
-        Some(("rm", matches)) => remove::handle(matches),
-        Some(("rate", matches)) => rate::handle(matches),
-        Some(("unrate", matches)) => unrate::handle(matches),
-        Some(("edit", matches)) => edit::handle(matches),
-        Some(("tags", matches)) => tags::handle(matches),
-        None => tui::run(&matches),
-        _ => unreachable!(),
-    }
+        .subcommand(stats::command())
+        .subcommand(doctor::command())
+        .subcommand(convert::command())
+        .subcommand(migrate::command())
+        .subcommand(encrypt::command())
+        .subcommand(decrypt::command())
+        .subcommand(pick::command())
+        .subcommand(config::command())
+        .subcommand(completions::command())
+        .subcommand(batch::command())
+        .subcommand(manpage::command())
 }
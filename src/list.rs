@@ -1,41 +1,74 @@
 use anyhow::Result;
-use clap::{ArgMatches, Command};
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
 
-use crate::{arg_util, args, media};
+use crate::{arg_util, args, config, media, query::Query};
 
 pub fn command() -> Command {
     Command::new("ls")
         .visible_aliases(["list"])
         .about("List items")
+        .after_long_help(args::TERM_HELP)
         .arg_required_else_help(false)
         .arg(args::term().help("Terms to search for (tag, year)"))
         .arg(args::note_bool().help("Whether to display notes"))
         .arg(args::tags_bool().help("Whether to display tags"))
+        .arg(args::seen_bool().help("Whether to display when items were last seen"))
+        .arg(
+            Arg::new("IDS")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("ids")
+                .help("Whether to display IDs, which can be used instead of names"),
+        )
+        .arg(
+            Arg::new("ALL_PROFILES")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("all-profiles")
+                .conflicts_with("DB")
+                .help("List items of all profiles"),
+        )
 }
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
-    let repo = arg_util::repo_from_matches(matches)?;
-    let mut items = repo.get_all();
+    // Load repos, along with their profile names if listing all profiles
+    let repos = if *matches.get_one::<bool>("ALL_PROFILES").unwrap_or(&false) {
+        config::get()
+            .profiles()
+            .into_iter()
+            .map(|(name, path)| {
+                let repo = arg_util::open_repo(&path, arg_util::lenient_from_matches(matches))?;
+                Ok((Some(name), repo))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![(None, arg_util::repo_from_matches(matches)?)]
+    };
+
+    let mut items: Vec<(Option<&str>, &media::Media)> = repos
+        .iter()
+        .flat_map(|(name, repo)| repo.get_all().into_iter().map(move |m| (name.as_deref(), m)))
+        .collect();
 
+    let defaults = &config::get().ls;
     let options = media::format::ListOptions {
-        note: *matches.get_one::<bool>("NOTE").unwrap_or(&false),
-        tags: *matches.get_one::<bool>("TAGS").unwrap_or(&false),
+        note: *matches.get_one::<bool>("NOTE").unwrap_or(&false) || defaults.note,
+        tags: *matches.get_one::<bool>("TAGS").unwrap_or(&false) || defaults.tags,
+        last_seen: *matches.get_one::<bool>("SEEN").unwrap_or(&false) || defaults.seen,
+        ids: *matches.get_one::<bool>("IDS").unwrap_or(&false),
 
         // Get max rating BEFORE filtering
-        max_rating: items.iter().map(|m| m.rating.unwrap_or(0)).max().unwrap_or(0),
+        max_rating: repos.iter().map(|r| r.1.max_rating()).max().unwrap_or(0),
     };
 
-    let max_rating = options.max_rating;
-    for t in arg_util::terms_from_matches(matches) {
-        let (negated, term) = match t.strip_prefix('!') {
-            Some(s) if !s.is_empty() => (true, s),
-            _ => (false, t.as_str()),
-        };
-        items.retain(|i| matches_term(i, term, max_rating) != negated);
-    }
+    let query = Query::new(arg_util::terms_from_matches(matches));
+    items.retain(|(_, i)| query.matches(i, options.max_rating));
 
     // Sort (watchlist, rating, unrated, alphabetic)
-    items.sort_by(|a, b| {
+    items.sort_by(|(_, a), (_, b)| {
         let a_weight = get_weight(a);
         let b_weight = get_weight(b);
 
@@ -47,18 +80,49 @@ pub fn handle(matches: &ArgMatches) -> Result<()> {
     });
 
     // Print
-    for item in &items {
-        println!("{}", item.as_line(&options));
+    for (profile, item) in &items {
+        match profile {
+            Some(p) => println!("{} {}", format!("[{p}]").dimmed(), item.as_line(&options)),
+            None => println!("{}", item.as_line(&options)),
+        }
     }
 
     Ok(())
 }
 
 fn get_weight(item: &media::Media) -> usize {
-    item.rating.unwrap_or(0) as usize + 1 + if item.has_tag("watchlist") { 1000 } else { 0 }
+    item.rating.unwrap_or(0) as usize + 1 + if item.on_watchlist() { 1000 } else { 0 }
+}
+
+/// Whether an item matches all given terms. Terms prefixed with `!` are
+/// negated.
+pub fn matches_terms(item: &media::Media, terms: &[impl AsRef<str>], max_rating: u8) -> bool {
+    matches_terms_with(item, terms, max_rating, |tag| item.has_tag_within(tag))
+}
+
+/// Like `matches_terms`, but with a custom check whether the item has a tag,
+/// e.g. using the repo's tag index
+pub fn matches_terms_with(
+    item: &media::Media,
+    terms: &[impl AsRef<str>],
+    max_rating: u8,
+    has_tag: impl Fn(&str) -> bool,
+) -> bool {
+    terms.iter().all(|t| {
+        let t = t.as_ref();
+        match t.strip_prefix('!') {
+            Some(term) if !term.is_empty() => !matches_term(item, term, max_rating, &has_tag),
+            _ => matches_term(item, t, max_rating, &has_tag),
+        }
+    })
 }
 
-pub fn matches_term(item: &media::Media, term: &str, max_rating: u8) -> bool {
+fn matches_term(
+    item: &media::Media,
+    term: &str,
+    max_rating: u8,
+    has_tag: &impl Fn(&str) -> bool,
+) -> bool {
     if let Some(range) = try_parse_year_range(term) {
         return matches!(item.year, Some(y) if y >= range.0 && y <= range.1);
     }
@@ -71,7 +135,7 @@ pub fn matches_term(item: &media::Media, term: &str, max_rating: u8) -> bool {
     if let Some(m) = try_match_rating(term, item, max_rating) {
         return m;
     }
-    if item.has_tag(term) {
+    if has_tag(term) {
         return true;
     }
     item.name.to_lowercase().contains(&term.to_lowercase())
@@ -1,6 +1,9 @@
 fn main() {
     if let Err(e) = mtracker::run() {
         eprintln!("{e}");
-        std::process::exit(1);
+
+        // Let scripts tell errors apart, e.g. "not found" from a corrupt database
+        let code = e.downcast_ref::<mtracker::Error>().map_or(1, mtracker::Error::exit_code);
+        std::process::exit(code);
     }
 }
@@ -0,0 +1,35 @@
+use std::{io, path::PathBuf};
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+pub fn command() -> Command {
+    Command::new("manpage")
+        .about("Generate man pages")
+        .long_about(
+            "Generate man pages
+
+Without a directory, the man page of the main command is printed. With a
+directory, man pages for all subcommands are written as well.
+
+Example: mtracker manpage ~/.local/share/man/man1",
+        )
+        .arg(
+            Arg::new("DIR")
+                .required(false)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Directory to write man pages to"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches, root: Command) -> Result<()> {
+    match matches.get_one::<PathBuf>("DIR") {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            clap_mangen::generate_to(root, dir)?;
+            println!("Man pages written to: {}", dir.to_string_lossy());
+        }
+        None => clap_mangen::Man::new(root).render(&mut io::stdout())?,
+    }
+    Ok(())
+}
@@ -1,18 +1,41 @@
-use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    error::{Error, Result},
+};
 
 pub mod format;
 pub mod handle;
 pub mod parser;
 pub mod repo;
-
-#[derive(Debug, PartialEq)]
+pub mod schema;
+pub mod storage;
+pub mod tag;
+
+/// A movie, series or anything else to keep track of
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
 pub struct Media {
     pub name: String,
+    /// Short ID that stays the same when the item is renamed, e.g. "a1b2"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub year: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rating: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub note: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub last_seen: Option<chrono::NaiveDate>,
+    /// File the item was included from, or `None` if it is in the database
+    /// file itself (or not saved yet)
+    #[serde(skip)]
+    pub origin: Option<std::path::PathBuf>,
 }
 
 /// Media is identified by its name + year ("name (year)") OR just its name if
@@ -21,11 +44,13 @@ impl Media {
     pub fn new(name: impl Into<String>, year: Option<u16>) -> Self {
         Media {
             name: name.into(),
+            id: None,
             year,
             rating: None,
             tags: vec![],
             note: String::new(),
             last_seen: None,
+            origin: None,
         }
     }
 
@@ -33,32 +58,57 @@ impl Media {
         Self::new(handle.name.clone(), handle.year)
     }
 
+    pub fn handle(&self) -> handle::Handle {
+        handle::Handle {
+            name: self.name.clone(),
+            year: self.year,
+        }
+    }
+
     pub fn matches_handle(&self, handle: &handle::Handle) -> bool {
         self.name == handle.name && self.year == handle.year
     }
 
+    /// Add a tag in its canonical form (see `tag::normalize`)
     pub fn add_tag(&mut self, tag: &str) {
-        if !self.tags.iter().any(|t| t == tag) {
+        let tag = tag::normalize(tag);
+        if !self.has_tag(tag) {
             self.tags.push(tag.into());
         }
     }
 
     pub fn remove_tag(&mut self, tag: &str) -> Result<()> {
-        if let Some(index) = self.tags.iter().position(|t| t == tag) {
+        let tag = tag::normalize(tag);
+        if let Some(index) = self.tags.iter().position(|t| tag::normalize(t) == tag) {
             self.tags.remove(index);
             Ok(())
         } else {
-            Err(anyhow!("Tag not found"))
+            Err(Error::TagNotFound(tag.into()))
         }
     }
 
     pub fn on_watchlist(&self) -> bool {
-        self.has_tag("watchlist")
+        self.has_tag(&config::get().watchlist_tag)
     }
 
+    /// Whether the item has the tag, in any spelling of its aliases
     pub fn has_tag(&self, tag: &str) -> bool {
-        self.tags.iter().any(|t| t == tag)
+        let tag = tag::normalize(tag);
+        self.tags.iter().any(|t| tag::normalize(t) == tag)
     }
+
+    /// Whether the item has the tag or one of its descendants, e.g.: "horror"
+    /// matches "horror/slasher"
+    pub fn has_tag_within(&self, tag: &str) -> bool {
+        let tag = tag::normalize(tag);
+        self.tags.iter().any(|t| tag::is_within(tag::normalize(t), tag))
+    }
+}
+
+/// Whether an ID can be written to the database and used as a handle, e.g.
+/// "#a1b2"
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 #[cfg(test)]
@@ -80,6 +130,31 @@ mod tests {
         assert_eq!(m.tags, vec!["horror"]);
     }
 
+    #[test]
+    fn has_tag_within() {
+        let mut m = Media::new("Test", None);
+        m.add_tag("horror/slasher");
+        assert!(m.has_tag_within("horror"));
+        assert!(m.has_tag_within("horror/slasher"));
+        assert!(!m.has_tag("horror"));
+        assert!(!m.has_tag_within("horror/gore"));
+    }
+
+    #[test]
+    fn has_aliased_tag() {
+        tag::set_aliases(tag::Aliases::parse("mystery: whodunit").unwrap());
+        let mut m = Media::new("Test", None);
+        m.tags = vec!["whodunit".into()];
+        assert!(m.has_tag("mystery"));
+        assert!(m.has_tag("whodunit"));
+        assert!(m.has_tag_within("mystery"));
+
+        m.add_tag("mystery");
+        assert_eq!(m.tags, vec!["whodunit"]);
+        m.remove_tag("mystery").unwrap();
+        assert!(m.tags.is_empty());
+    }
+
     #[test]
     fn remove_tag() {
         let mut m = Media::new("Test", None);
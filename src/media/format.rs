@@ -1,14 +1,19 @@
 use colored::Colorize;
 
-use crate::media;
+use crate::{config, media};
 
+/// What `Media::as_line` shows besides rating, name and year
+#[derive(Clone, Debug, Default)]
 pub struct ListOptions {
     pub note: bool,
     pub tags: bool,
+    pub last_seen: bool,
+    pub ids: bool,
     pub max_rating: u8,
 }
 
 impl media::Media {
+    /// Format the item for listing, with colors
     pub fn as_line(&self, options: &ListOptions) -> String {
         let mut result = String::new();
 
@@ -26,10 +31,19 @@ impl media::Media {
             result += &format!(" ({year})").dimmed().to_string();
         }
 
+        if let (true, Some(id)) = (options.ids, &self.id) {
+            result += &format!(" #{id}").dimmed().to_string();
+        }
+
         if options.tags && !self.tags.is_empty() {
             result += &format!(" [{}]", self.tags.join(", "));
         }
 
+        if let (true, Some(date)) = (options.last_seen, self.last_seen) {
+            let date = date.format(&config::get().date_format).to_string();
+            result += &format!(" (seen {date})").dimmed().to_string();
+        }
+
         if options.note && !&self.note.is_empty() {
             result += &format!(": {}", self.note);
         }
@@ -61,6 +75,8 @@ mod tests {
             max_rating,
             note,
             tags,
+            last_seen: false,
+            ids: false,
         }
     }
 
@@ -94,6 +110,27 @@ mod tests {
         assert_eq!(line, "Alien [horror, sci-fi]");
     }
 
+    #[test]
+    fn as_line_with_last_seen() {
+        setup();
+        let mut m = Media::new("Alien", None);
+        m.last_seen = chrono::NaiveDate::from_ymd_opt(2024, 6, 12);
+        let mut options = opts(0, false, false);
+        options.last_seen = true;
+        assert_eq!(m.as_line(&options), "Alien (seen 2024-06-12)");
+    }
+
+    #[test]
+    fn as_line_with_id() {
+        setup();
+        let mut m = Media::new("Alien", Some(1979));
+        m.id = Some("a1b2".into());
+        assert_eq!(m.as_line(&opts(0, false, false)), "Alien (1979)");
+        let mut options = opts(0, false, false);
+        options.ids = true;
+        assert_eq!(m.as_line(&options), "Alien (1979) #a1b2");
+    }
+
     #[test]
     fn as_line_with_rating() {
         setup();
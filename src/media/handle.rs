@@ -4,6 +4,7 @@ use regex::Regex;
 
 static HANDLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.+)\s\((\d{4})\)$").unwrap());
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Handle {
     pub name: String,
     pub year: Option<u16>,
@@ -22,6 +23,13 @@ impl Handle {
             },
         }
     }
+
+    /// The ID if the handle may refer to an item by it, e.g. "#a1b2". It is
+    /// a name if no item has that ID.
+    pub fn id(&self) -> Option<&str> {
+        let id = self.name.strip_prefix('#')?;
+        (self.year.is_none() && super::is_valid_id(id)).then_some(id)
+    }
 }
 
 impl std::fmt::Display for Handle {
@@ -46,5 +54,10 @@ mod tests {
         let handle = Handle::from_user_input("Alien");
         assert_eq!(handle.name, "Alien");
         assert_eq!(handle.year, None);
+        assert_eq!(handle.id(), None);
+
+        assert_eq!(Handle::from_user_input("#a1b2").id(), Some("a1b2"));
+        assert_eq!(Handle::from_user_input("#1 (2020)").id(), None);
+        assert_eq!(Handle::from_user_input("# 1").id(), None);
     }
 }
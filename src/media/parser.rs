@@ -1,44 +1,187 @@
-use anyhow::{anyhow, Result};
 use chrono;
 
 use crate::media;
 
 // (key, value)
-fn parse_prop<'a, T: std::str::FromStr>(arg: (&'a str, &'a str)) -> Result<Option<T>>
+fn parse_prop<'a, T: std::str::FromStr>(arg: (&'a str, &'a str)) -> Result<T, ParseErrorKind>
 where
     <T as std::str::FromStr>::Err: std::fmt::Display,
 {
-    match str::parse::<T>(arg.1) {
-        Ok(v) => Ok(Some(v)),
-        Err(e) => Err(anyhow!("failed to parse {}: {e}", arg.0)),
-    }
+    str::parse::<T>(arg.1).map_err(|e| ParseErrorKind::InvalidValue {
+        key: arg.0.to_string(),
+        message: e.to_string(),
+    })
+}
+
+fn parse_last_seen(input: &str) -> Result<chrono::NaiveDate, ParseErrorKind> {
+    chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d").map_err(|e| ParseErrorKind::InvalidValue {
+        key: "last_seen".into(),
+        message: format!("{e}\nExpected format: 2024-12-31"),
+    })
 }
 
-fn parse_last_seen(input: &str) -> Result<Option<chrono::NaiveDate>> {
-    match chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d") {
-        Ok(date) => Ok(Some(date)),
-        Err(e) => Err(anyhow!("failed to parse last_seen: {e}\nExpected format: 2024-12-31")),
+fn parse_id(input: &str) -> Result<String, ParseErrorKind> {
+    if media::is_valid_id(input) {
+        Ok(input.to_string())
+    } else {
+        Err(ParseErrorKind::InvalidValue {
+            key: "id".into(),
+            message: "only letters, digits, '-' and '_' are allowed".into(),
+        })
     }
 }
 
-fn parse_tags(input: &str) -> Result<Vec<String>> {
-    let tags: Vec<String> = parse_prop::<String>(("tags", input))?
-        .unwrap()
-        .split(',')
-        .map(str::trim)
-        .map(str::to_string)
-        .collect();
+fn parse_tags(input: &str) -> Result<Vec<String>, ParseErrorKind> {
+    let tags: Vec<String> = input.split(',').map(str::trim).map(str::to_string).collect();
 
     if tags.contains(&String::new()) {
-        Err(anyhow!("empty tag"))
+        Err(ParseErrorKind::EmptyTag)
     } else {
         Ok(tags)
     }
 }
 
+/// Error in a database entry. Lines and columns start at 1.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("{kind}")]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, kind: ParseErrorKind) -> Self {
+        ParseError { line, column, kind }
+    }
+}
+
+/// What is wrong with a database entry
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    #[error("entry can't be empty")]
+    Empty,
+
+    #[error("illegal empty line")]
+    EmptyLine,
+
+    /// A line without ':', e.g.: "year 1979"
+    #[error("delimiter missing: {0}")]
+    MissingDelimiter(String),
+
+    #[error("unknown key: {0}")]
+    UnknownKey(String),
+
+    #[error("failed to parse {key}: {message}")]
+    InvalidValue { key: String, message: String },
+
+    #[error("empty tag")]
+    EmptyTag,
+
+    /// An include that can't be followed, e.g. because the file doesn't exist
+    #[error("invalid include: {0}")]
+    InvalidInclude(String),
+}
+
+/// An entry of the database, along with the number of its first line and its
+/// byte offset in the database
+#[derive(Debug, PartialEq)]
+pub struct Block<'a> {
+    pub line: usize,
+    pub offset: usize,
+    pub text: &'a str,
+}
+
+/// Start of a line that includes other files, e.g. "include: movies.txt"
+const INCLUDE: &str = "include:";
+
+impl<'a> Block<'a> {
+    /// Parse the entry. Errors point at the line within the whole database.
+    pub fn parse(&self) -> Result<media::Media, ParseError> {
+        media::Media::from_db_entry(self.text)
+            .map_err(|e| ParseError::new(self.line + e.line - 1, e.column, e.kind))
+    }
+
+    /// Paths of an include block, e.g. "include: movies.txt", along with their
+    /// line numbers. `None` if the block is an entry.
+    pub fn includes(&self) -> Option<Result<Vec<(usize, &'a str)>, ParseError>> {
+        if !self.text.starts_with(INCLUDE) {
+            return None;
+        }
+
+        let includes = self.text.lines().enumerate().map(|(i, line)| {
+            let line_number = self.line + i;
+            let error = |message: &str| {
+                let kind = ParseErrorKind::InvalidInclude(message.into());
+                ParseError::new(line_number, 1, kind)
+            };
+            match line.strip_prefix(INCLUDE).map(str::trim) {
+                Some("") => Err(error("path missing")),
+                Some(path) => Ok((line_number, path)),
+                None => Err(error("an include block can only contain includes")),
+            }
+        });
+        Some(includes.collect())
+    }
+}
+
+/// Parse text that must contain exactly one entry
+pub fn parse_single(text: &str) -> Result<media::Media, ParseError> {
+    match blocks(text).as_slice() {
+        [] => Err(ParseError::new(1, 1, ParseErrorKind::Empty)),
+        [block] => block.parse(),
+        [_, next, ..] => Err(ParseError::new(next.line, 1, ParseErrorKind::EmptyLine)),
+    }
+}
+
+/// Split the content of a database into entries, separated by blank lines.
+/// The header with the format version is skipped.
+pub fn blocks(content: &str) -> Vec<Block<'_>> {
+    let mut blocks = vec![];
+    let mut start: Option<(usize, usize)> = None; // (line, byte offset)
+    let mut offset = 0;
+
+    let block = |line, range: std::ops::Range<usize>| {
+        let text = content[range.clone()].trim();
+        let leading = content[range.clone()].len() - content[range.clone()].trim_start().len();
+        Block {
+            line,
+            offset: range.start + leading,
+            text,
+        }
+    };
+
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        if i == 0 && line.starts_with(media::schema::HEADER) {
+            offset += line.len();
+            continue;
+        }
+        match (line.trim().is_empty(), start) {
+            (false, None) => start = Some((i + 1, offset)),
+            (true, Some((line, from))) => {
+                blocks.push(block(line, from..offset));
+                start = None;
+            }
+            _ => {}
+        }
+        offset += line.len();
+    }
+    if let Some((line, from)) = start {
+        blocks.push(block(line, from..content.len()));
+    }
+
+    blocks
+}
+
 impl media::Media {
-    #[allow(clippy::missing_panics_doc)]
-    pub fn from_db_entry(entry: &str) -> Result<Self> {
+    /// Parse a single database entry
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        parse_single(text)
+    }
+
+    pub(crate) fn from_db_entry(entry: &str) -> Result<Self, ParseError> {
+        let mut id: Option<String> = None;
         let mut year: Option<u16> = None;
         let mut rating: Option<u8> = None;
         let mut note: String = String::new();
@@ -50,44 +193,51 @@ impl media::Media {
         // First line is always the name
         let name = match lines.next() {
             Some(n) => n.to_string(),
-            None => {
-                return Err(anyhow!("entry can't be empty"));
-            }
+            None => return Err(ParseError::new(1, 1, ParseErrorKind::Empty)),
         };
 
         // Subsequent lines are key:value pairs
-        for line in lines {
+        for (i, line) in lines.enumerate() {
+            let at = |column: usize| move |kind| ParseError::new(i + 2, column, kind);
+
             if line.is_empty() {
-                return Err(anyhow!("illegal empty line"));
+                return Err(at(1)(ParseErrorKind::EmptyLine));
             }
 
-            let (key, value) = match line.split_once(':') {
-                Some((n, v)) => (n, v.trim()),
-                None => return Err(anyhow!("delimiter missing: {line}")),
-            };
+            let (key, raw_value) = line
+                .split_once(':')
+                .ok_or_else(|| at(1)(ParseErrorKind::MissingDelimiter(line.into())))?;
+            let value = raw_value.trim();
+            let column = line[..line.len() - raw_value.trim_start().len()].chars().count() + 1;
 
             match key {
-                "year" => year = parse_prop::<u16>((key, value))?,
-                "rating" => rating = parse_prop::<u8>((key, value))?,
-                "note" => note = parse_prop::<String>((key, value))?.unwrap(),
-                "tags" => tags = parse_tags(value)?,
-                "last_seen" => last_seen = parse_last_seen(value)?,
-                _ => return Err(anyhow!("unknown key: {key}")),
+                "id" => id = Some(parse_id(value).map_err(at(column))?),
+                "year" => year = Some(parse_prop((key, value)).map_err(at(column))?),
+                "rating" => rating = Some(parse_prop((key, value)).map_err(at(column))?),
+                "note" => note = value.to_string(),
+                "tags" => tags = parse_tags(value).map_err(at(column))?,
+                "last_seen" => last_seen = Some(parse_last_seen(value).map_err(at(column))?),
+                _ => return Err(at(1)(ParseErrorKind::UnknownKey(key.into()))),
             };
         }
 
         Ok(Self {
             name,
+            id,
             year,
             rating,
             tags,
             note,
             last_seen,
+            origin: None,
         })
     }
 
     pub fn to_db_entry(&self) -> String {
         let mut result = String::from(&self.name);
+        if let Some(id) = &self.id {
+            result += format!("\nid: {id}").as_str();
+        }
         if let Some(year) = self.year {
             result += format!("\nyear: {year}").as_str();
         }
@@ -146,58 +296,144 @@ note:very long";
         // Empty entry
         let entry = "";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("entry can't be empty"));
+        assert!(matches!(error.kind, ParseErrorKind::Empty));
 
         // Illegal empty lines in between
         let entry = "foobar
 
 year: 2009";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("illegal empty line"));
+        assert!(matches!(error.kind, ParseErrorKind::EmptyLine));
 
         // Not a number
         let entry = "foobar
 year: invalid";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("failed to parse year"));
+        assert!(matches!(error.kind, ParseErrorKind::InvalidValue { key, .. } if key == "year"));
 
         // Invalid number
         let entry = "foobar
 rating: -4";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("failed to parse rating"));
+        assert!(matches!(error.kind, ParseErrorKind::InvalidValue { key, .. } if key == "rating"));
 
         // Non-existing key
         let entry = "foobar
 foo: bar";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("unknown key"));
+        assert!(matches!(error.kind, ParseErrorKind::UnknownKey(key) if key == "foo"));
 
         // Empty tags
         let entry = "foobar
 tags: a,";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("empty tag"));
+        assert!(matches!(error.kind, ParseErrorKind::EmptyTag));
 
         // Prop without delimiter
         let entry = "foobar
 name value";
         let error = media::Media::from_db_entry(entry).unwrap_err();
-        assert!(error.to_string().starts_with("delimiter missing"));
+        assert!(matches!(error.kind, ParseErrorKind::MissingDelimiter(_)));
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let content = "Alien
+year: 1979
+
+
+Aliens
+  \n\nPearl\n";
+        assert_eq!(
+            blocks(content),
+            vec![
+                Block {
+                    line: 1,
+                    offset: 0,
+                    text: "Alien\nyear: 1979"
+                },
+                Block {
+                    line: 5,
+                    offset: 19,
+                    text: "Aliens"
+                },
+                Block {
+                    line: 8,
+                    offset: 30,
+                    text: "Pearl"
+                },
+            ]
+        );
+
+        let content = "# mtracker-format: 1\nAlien";
+        assert_eq!(
+            blocks(content),
+            vec![Block {
+                line: 2,
+                offset: 21,
+                text: "Alien"
+            }]
+        );
+    }
+
+    #[test]
+    fn reads_includes() {
+        let content = "include: movies.txt
+include:  books/*.txt
+
+Alien
+
+include:
+include: x.txt
+year: 1979";
+        let blocks = blocks(content);
+        assert_eq!(blocks[0].includes(), Some(Ok(vec![(1, "movies.txt"), (2, "books/*.txt")])));
+        assert_eq!(blocks[1].includes(), None);
+
+        let error = blocks[2].includes().unwrap().unwrap_err();
+        assert_eq!((error.line, error.column), (6, 1));
+        assert!(matches!(error.kind, ParseErrorKind::InvalidInclude(_)));
+    }
+
+    #[test]
+    fn locates_errors() {
+        let content = "Alien
+year: 1979
+
+Aliens
+rating:  -4";
+        let blocks = blocks(content);
+        assert!(blocks[0].parse().is_ok());
+
+        let error = blocks[1].parse().unwrap_err();
+        assert_eq!((error.line, error.column), (5, 10));
+        assert!(matches!(error.kind, ParseErrorKind::InvalidValue { key, .. } if key == "rating"));
+
+        let error = Block {
+            line: 3,
+            offset: 0,
+            text: "foobar\nfoo: bar",
+        }
+        .parse()
+        .unwrap_err();
+        assert_eq!((error.line, error.column), (4, 1));
     }
 
     #[test]
     fn media_to_db_entry() {
         let media = media::Media {
             name: "Forrest Gump".into(),
+            id: Some("a1b2".into()),
             year: Some(1994),
             rating: Some(2),
             tags: vec!["drama".into(), "romance".into()],
             note: "very long".into(),
             last_seen: chrono::NaiveDate::from_ymd_opt(2024, 6, 12),
+            origin: None,
         };
 
         let expected = "Forrest Gump
+id: a1b2
 year: 1994
 rating: 2
 tags: drama, romance
@@ -205,5 +441,6 @@ note: very long
 last_seen: 2024-06-12";
 
         assert_eq!(media.to_db_entry(), expected);
+        assert_eq!(media::Media::from_db_entry(expected).unwrap(), media);
     }
 }
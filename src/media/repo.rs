@@ -1,51 +1,279 @@
-use std::{fs, path};
-
-use anyhow::{anyhow, Result};
-
-use crate::media;
-
+use std::{
+    borrow::Borrow,
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    hash::Hash,
+    path,
+};
+
+use crate::{
+    config,
+    error::{Error, InvalidEntry, Result},
+    media::{
+        self,
+        storage::{self, Kind, Storage},
+        tag,
+    },
+};
+
+/// A database of items, kept in memory until `write` is called
 pub struct Repo {
     pub path: path::PathBuf,
     items: Vec<media::Media>,
+    // Entries that were skipped in lenient mode
+    errors: Vec<InvalidEntry>,
+    kind: Kind,
+    storage: Box<dyn Storage>,
+    // Built on first use. Items borrowed mutably are taken out of it and added
+    // again when it is used next.
+    index: RefCell<Option<Index>>,
+    // Changes since the items were read or written, while there are few enough
+    // to write them one by one
+    changes: Option<Changes>,
+}
+
+// Beyond this, writing all items at once is faster
+const MAX_CHANGES: usize = 16;
+
+/// Items that changed since they were read or written
+#[derive(Debug, Default)]
+struct Changes {
+    // Positions of changed or added items, with the items as they were stored
+    // if they were
+    updated: Vec<(usize, Option<media::Media>)>,
+    // Stored items that were removed
+    removed: Vec<media::Media>,
+}
+
+/// Positions of items by handle and by tag
+#[derive(Debug, Default)]
+struct Index {
+    // Several items only have the same handle in invalid databases
+    handles: HashMap<media::handle::Handle, Vec<usize>>,
+    // Includes parent tags, e.g. "horror" for "horror/slasher"
+    tags: HashMap<String, Vec<usize>>,
+    // Several items only have the same ID in invalid databases
+    ids: HashMap<String, Vec<usize>>,
+    // Items that were taken out, as they may have changed since
+    stale: Vec<usize>,
+}
+
+impl Index {
+    fn new(items: &[media::Media]) -> Self {
+        let mut index = Index::default();
+        for (i, item) in items.iter().enumerate() {
+            index.insert(i, item);
+        }
+        index
+    }
+
+    // Add the item at position `i`
+    fn insert(&mut self, i: usize, item: &media::Media) {
+        let add = |positions: &mut Vec<usize>| {
+            if let Err(p) = positions.binary_search(&i) {
+                positions.insert(p, i);
+            }
+        };
+        add(self.handles.entry(item.handle()).or_default());
+        if let Some(id) = &item.id {
+            add(self.ids.entry(id.clone()).or_default());
+        }
+        for tag in tags(item) {
+            add(self.tags.entry(tag.to_string()).or_default());
+        }
+    }
+
+    // Take out the item at position `i` until `refresh`, without moving others
+    fn take(&mut self, i: usize, item: &media::Media) {
+        unlink(&mut self.handles, &item.handle(), i);
+        if let Some(id) = &item.id {
+            unlink(&mut self.ids, id, i);
+        }
+        for tag in tags(item) {
+            unlink(&mut self.tags, tag, i);
+        }
+        self.stale.push(i);
+    }
+
+    // Add the items that were taken out again
+    fn refresh(&mut self, items: &[media::Media]) {
+        let mut stale = std::mem::take(&mut self.stale);
+        stale.sort_unstable();
+        stale.dedup();
+        for i in stale {
+            self.insert(i, &items[i]);
+        }
+    }
+
+    // Remove the item at position `i`, moving the ones after it
+    fn remove(&mut self, i: usize) {
+        for positions in self
+            .handles
+            .values_mut()
+            .chain(self.tags.values_mut())
+            .chain(self.ids.values_mut())
+        {
+            positions.retain(|&p| p != i);
+            positions.iter_mut().filter(|p| **p > i).for_each(|p| *p -= 1);
+        }
+        self.handles.retain(|_, positions| !positions.is_empty());
+        self.tags.retain(|_, positions| !positions.is_empty());
+        self.ids.retain(|_, positions| !positions.is_empty());
+        self.stale.retain(|&p| p != i);
+        self.stale.iter_mut().filter(|p| **p > i).for_each(|p| *p -= 1);
+    }
+}
+
+// Tags of an item in the index, with their parents
+fn tags(item: &media::Media) -> Vec<&str> {
+    let mut tags: Vec<&str> =
+        item.tags.iter().flat_map(|t| tag::ancestors(tag::normalize(t))).collect();
+    tags.sort_unstable();
+    tags.dedup();
+    tags
+}
+
+// Remove position `i` from the positions of a key, and the key if none are left
+fn unlink<K, Q>(map: &mut HashMap<K, Vec<usize>>, key: &Q, i: usize)
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq + ?Sized,
+{
+    if let Some(positions) = map.get_mut(key) {
+        if let Ok(p) = positions.binary_search(&i) {
+            positions.remove(p);
+        }
+        if positions.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+/// Result of looking up an item by a handle the user entered
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    /// The handle matches exactly one item
+    Found(usize),
+    /// The handle matches several items (ignoring case, and the year if not
+    /// given)
+    Ambiguous(Vec<usize>),
+    /// The handle matches no item, but these items' names contain its name
+    NotFound(Vec<usize>),
 }
 
 impl Repo {
     pub fn new(path: &path::Path) -> Result<Self> {
-        let mut repo = Repo {
+        Self::open(path, false)
+    }
+
+    /// Read a database: a text file along with the files it includes, a
+    /// directory with one file per item, an SQLite database, a JSON, YAML or
+    /// TOML file or an encrypted text file. In lenient mode, invalid entries
+    /// are skipped and kept as they are when writing, instead of failing.
+    pub fn open(path: &path::Path, lenient: bool) -> Result<Self> {
+        let kind = Kind::detect(path);
+        let mut storage = storage::open(path, kind).map_err(Error::storage)?;
+        let loaded = storage.load().map_err(Error::storage)?;
+        if !lenient && !loaded.errors.is_empty() {
+            return Err(Error::InvalidEntries(loaded.errors));
+        }
+
+        Ok(Repo {
             path: path.to_path_buf(),
-            items: vec![],
-        };
-        repo.read()?;
-        Ok(repo)
+            items: loaded.items,
+            errors: loaded.errors,
+            kind,
+            storage,
+            index: RefCell::new(None),
+            changes: Some(Changes::default()),
+        })
+    }
+
+    /// Errors of entries that were skipped in lenient mode, e.g.:
+    /// "db.txt:12:7: failed to parse year"
+    pub fn errors(&self) -> &[InvalidEntry] {
+        &self.errors
     }
 
     pub fn get(&mut self, handle: &media::handle::Handle) -> Option<&mut media::Media> {
-        self.items.iter_mut().find(|m| m.matches_handle(handle))
+        let i = self.position(handle)?;
+        self.take(i);
+        Some(&mut self.items[i])
+    }
+
+    pub fn contains(&self, handle: &media::handle::Handle) -> bool {
+        self.position(handle).is_some()
+    }
+
+    /// Sorted positions of items with the given tag or one of its descendants
+    pub fn tagged(&self, tag: &str) -> Vec<usize> {
+        self.index().tags.get(tag::normalize(tag)).cloned().unwrap_or_default()
     }
 
-    pub fn get_or_create(&mut self, handle: &media::handle::Handle) -> Result<&mut media::Media> {
-        if self.get(handle).is_none() {
-            self.add(media::Media::from_handle(handle))?;
-            println!("Added new item: {handle}");
+    /// Find an item by exact handle, then by name ignoring case (and the year,
+    /// if the handle has none), then by part of the name
+    pub fn lookup(&self, handle: &media::handle::Handle) -> Lookup {
+        if let Some(i) = self.position(handle) {
+            return Lookup::Found(i);
         }
 
-        Ok(self.get(handle).unwrap())
+        let name = handle.name.to_lowercase();
+        let find = |f: &dyn Fn(&str) -> bool| -> Vec<usize> {
+            (0..self.items.len())
+                .filter(|&i| {
+                    let item = &self.items[i];
+                    f(&item.name.to_lowercase()) && handle.year.is_none_or(|y| item.year == Some(y))
+                })
+                .collect()
+        };
+
+        let same = find(&|n| n == name);
+        match same.len() {
+            0 => Lookup::NotFound(find(&|n| n.contains(&name))),
+            1 => Lookup::Found(same[0]),
+            _ => Lookup::Ambiguous(same),
+        }
+    }
+
+    /// Highest possible rating: the configured scale, or the highest rating
+    pub fn max_rating(&self) -> u8 {
+        config::get()
+            .rating_scale
+            .unwrap_or_else(|| self.items.iter().filter_map(|m| m.rating).max().unwrap_or(0))
     }
 
     pub fn len(&self) -> usize {
         self.items.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
     pub fn get_by_index(&self, i: usize) -> &media::Media {
         &self.items[i]
     }
 
     pub fn get_by_index_mut(&mut self, i: usize) -> &mut media::Media {
+        self.take(i);
         &mut self.items[i]
     }
 
     pub fn remove_by_index(&mut self, i: usize) {
-        self.items.swap_remove(i);
+        let item = self.items.remove(i);
+        if let Some(index) = self.index.get_mut() {
+            index.remove(i);
+        }
+
+        if let Some(changes) = &mut self.changes {
+            let stored = match changes.updated.iter().position(|(p, _)| *p == i) {
+                Some(c) => changes.updated.remove(c).1,
+                None => Some(item),
+            };
+            changes.removed.extend(stored);
+            changes.updated.iter_mut().filter(|(p, _)| *p > i).for_each(|(p, _)| *p -= 1);
+            self.limit_changes();
+        }
     }
 
     pub fn get_all(&self) -> Vec<&media::Media> {
@@ -62,58 +290,229 @@ impl Repo {
                 f(item);
                 Ok(())
             }
-            None => Err(anyhow!("item not found: {handle}")),
+            None => Err(Error::NotFound(handle.clone())),
         }
     }
 
-    pub fn add(&mut self, item: media::Media) -> Result<()> {
+    /// Add an item at the end. It gets a new ID unless it has one already,
+    /// which must not be in use.
+    pub fn add(&mut self, mut item: media::Media) -> Result<()> {
+        match &item.id {
+            Some(id) => self.check_id(id, self.items.len())?,
+            None => item.id = Some(self.new_id()),
+        }
+        if let Some(index) = self.index.get_mut() {
+            index.insert(self.items.len(), &item);
+        }
+        if let Some(changes) = &mut self.changes {
+            changes.updated.push((self.items.len(), None));
+            self.limit_changes();
+        }
         self.items.push(item);
         Ok(())
     }
 
+    /// Replace an item, keeping its position in the file. It stays in the file
+    /// it was included from, unless the new item has another origin.
+    pub fn replace(
+        &mut self,
+        handle: &media::handle::Handle,
+        mut item: media::Media,
+    ) -> Result<()> {
+        let index = self.position(handle).ok_or_else(|| Error::NotFound(handle.clone()))?;
+        self.check_collision(&item.handle(), index)?;
+        if let Some(id) = &item.id {
+            self.check_id(id, index)?;
+        }
+        self.take(index);
+        if item.origin.is_none() {
+            item.origin = self.items[index].origin.take();
+        }
+        if item.id.is_none() {
+            item.id = self.items[index].id.take();
+        }
+        self.items[index] = item;
+        Ok(())
+    }
+
+    /// Change name and year of an item, keeping its position in the file
+    pub fn rename(
+        &mut self,
+        handle: &media::handle::Handle,
+        new_handle: &media::handle::Handle,
+    ) -> Result<()> {
+        let index = self.position(handle).ok_or_else(|| Error::NotFound(handle.clone()))?;
+        self.check_collision(new_handle, index)?;
+        self.take(index);
+        let item = &mut self.items[index];
+        item.name.clone_from(&new_handle.name);
+        item.year = new_handle.year;
+        Ok(())
+    }
+
+    fn position(&self, handle: &media::handle::Handle) -> Option<usize> {
+        let index = self.index();
+        // Names may start with '#' as well, e.g. "#Alive"
+        let by_id = handle.id().and_then(|id| index.ids.get(id));
+        by_id.or_else(|| index.handles.get(handle)).map(|positions| positions[0])
+    }
+
+    // Random hexadecimal ID that no item has, as short as possible
+    fn new_id(&self) -> String {
+        let index = self.index();
+        let ids = &index.ids;
+        (4..)
+            .flat_map(|len| (0..16).map(move |_| len))
+            .map(|len| (0..len).map(|_| fastrand::digit(16)).collect::<String>())
+            .find(|id| !ids.contains_key(id))
+            .unwrap()
+    }
+
+    fn index(&self) -> Ref<'_, Index> {
+        let outdated = self.index.borrow().as_ref().is_none_or(|i| !i.stale.is_empty());
+        if outdated {
+            let mut index = self.index.borrow_mut();
+            index.get_or_insert_with(|| Index::new(&self.items)).refresh(&self.items);
+        }
+        Ref::map(self.index.borrow(), |i| i.as_ref().unwrap())
+    }
+
+    // Take an item out of the index while it is borrowed mutably, as its handle
+    // or tags may change. Once many items changed, rebuilding it is faster.
+    fn take(&mut self, i: usize) {
+        let index = self.index.get_mut();
+        match index {
+            Some(idx) if idx.stale.len() < self.items.len() / 16 => idx.take(i, &self.items[i]),
+            _ => *index = None,
+        }
+
+        if let Some(changes) = &mut self.changes {
+            if changes.updated.iter().all(|(p, _)| *p != i) {
+                changes.updated.push((i, Some(self.items[i].clone())));
+                self.limit_changes();
+            }
+        }
+    }
+
+    // Stop keeping track of changes once there are too many to write them one
+    // by one
+    fn limit_changes(&mut self) {
+        let count = self.changes.as_ref().map_or(0, |c| c.updated.len() + c.removed.len());
+        if count > MAX_CHANGES {
+            self.changes = None;
+        }
+    }
+
+    // Fail if an item other than the one at `index` has the given handle
+    fn check_collision(&self, handle: &media::handle::Handle, index: usize) -> Result<()> {
+        match self.position(handle) {
+            Some(i) if i != index => Err(Error::AlreadyExists(handle.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    // Fail if an item other than the one at `index` has the given ID
+    fn check_id(&self, id: &str, index: usize) -> Result<()> {
+        if !media::is_valid_id(id) {
+            return Err(Error::invalid("id", format!("invalid ID: {id}")));
+        }
+        match self.index().ids.get(id) {
+            Some(positions) if positions.iter().any(|&i| i != index) => {
+                Err(Error::invalid("id", format!("already in use: {id}")))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn remove_by_handle(&mut self, handle: &media::handle::Handle) -> Result<()> {
-        match self.items.iter().position(|m| m.matches_handle(handle)) {
+        match self.position(handle) {
             Some(index) => {
-                self.items.swap_remove(index);
+                self.remove_by_index(index);
                 Ok(())
             }
-            None => Err(anyhow!("item not found: {}", &handle)),
+            None => Err(Error::NotFound(handle.clone())),
         }
     }
 
-    // Read all items from file into memory
-    fn read(&mut self) -> Result<()> {
-        let file_content = fs::read_to_string(&self.path).unwrap_or_default();
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
 
-        // Get blocks of text separated by empty lines
-        let blocks = file_content.split("\n\n").filter(|b| !b.is_empty()).map(str::trim);
+    /// Replace all items, e.g. after editing the whole database
+    pub fn set_all(&mut self, items: Vec<media::Media>) {
+        *self.index.get_mut() = None;
+        self.changes = None;
+        self.items = items;
+    }
 
-        // Parse blocks of text into media items
-        for block in blocks {
-            self.items.push(media::Media::from_db_entry(block)?);
-        }
+    /// Write all items. In an SQLite database, a few changed items are written
+    /// one by one instead, as the other rows stay the same.
+    pub fn write(&mut self) -> Result<()> {
+        let result = match self.changes.take() {
+            Some(changes) if self.kind == Kind::Sqlite && self.by_id(&changes) => {
+                self.write_changes(changes)
+            }
+            _ => self.storage.save(&self.items),
+        };
+        // After a failure, the stored items are unknown
+        self.changes = result.is_ok().then(Changes::default);
+        result.map_err(Error::storage)
+    }
 
-        Ok(())
+    /// Write all items in their canonical form, also the ones that didn't
+    /// change, e.g. to remove trailing whitespace
+    pub fn rewrite(&mut self) -> Result<()> {
+        let result = self.storage.rewrite(&self.items);
+        self.changes = result.is_ok().then(Changes::default);
+        result.map_err(Error::storage)
     }
 
-    /// Write all items to file
-    pub fn write(&self) -> Result<()> {
-        // Create path if it doesn't exist
-        std::fs::create_dir_all(self.path.parent().unwrap())?;
+    // Whether all changed items can be found in storage by their ID: they have
+    // one that didn't change, and no other item has it
+    fn by_id(&self, changes: &Changes) -> bool {
+        let index = self.index();
+        let updated = changes.updated.iter().all(|(i, stored)| {
+            let id = &self.items[*i].id;
+            let positions = id.as_ref().and_then(|id| index.ids.get(id));
+            stored.as_ref().is_none_or(|s| &s.id == id) && positions.is_some_and(|p| p == &[*i])
+        });
+        let removed = changes
+            .removed
+            .iter()
+            .all(|stored| stored.id.as_ref().is_some_and(|id| !index.ids.contains_key(id)));
+        updated && removed
+    }
 
-        let mut output = String::new();
-        for entry in self.items.iter().map(media::Media::to_db_entry) {
-            output += &entry;
-            output += "\n\n";
+    // Remove and upsert single items, see `by_id`
+    fn write_changes(&mut self, mut changes: Changes) -> anyhow::Result<()> {
+        for stored in changes.removed {
+            let id = stored.id.unwrap_or_default();
+            self.storage
+                .remove(&media::handle::Handle::from_user_input(&format!("#{id}")))?;
+        }
+        // Added items are at the end, so they are added in order
+        changes.updated.sort_unstable_by_key(|(i, _)| *i);
+        for (i, stored) in changes.updated {
+            if stored.as_ref() != Some(&self.items[i]) {
+                self.storage.upsert(self.items[i].clone())?;
+            }
         }
-        Ok(fs::write(&self.path, output.trim())?)
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
 
+    fn with_id(name: &str, year: Option<u16>, id: &str) -> media::Media {
+        let mut item = media::Media::new(name, year);
+        item.id = Some(id.into());
+        item
+    }
+
     #[test]
     fn reads() {
         let mut path = std::env::temp_dir();
@@ -173,18 +572,364 @@ year: 1984
         fs::remove_file(&path).ok();
 
         let mut repo = Repo::new(&path).unwrap();
-        repo.add(media::Media::new("Forrest Gump", Some(1994))).ok();
-        repo.add(media::Media::new("Alien", Some(1979))).ok();
+        repo.add(with_id("Forrest Gump", Some(1994), "f1")).ok();
+        repo.add(with_id("Alien", Some(1979), "a1")).ok();
         repo.write().unwrap();
 
         assert_eq!(
             fs::read_to_string(&path).unwrap(),
             "Forrest Gump
+id: f1
 year: 1994
 
 Alien
+id: a1
 year: 1979"
         );
         fs::remove_file(&path).ok();
     }
+
+    #[test]
+    fn reads_leniently() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_reads_leniently.txt");
+        fs::write(
+            &path,
+            "Alien
+year: 1979
+
+Aliens
+year: 86a
+
+Pearl
+foo: bar",
+        )
+        .unwrap();
+
+        // All errors are reported
+        let error = Repo::new(&path).err().unwrap();
+        assert!(matches!(&error, Error::InvalidEntries(entries) if entries.len() == 2));
+        let error = error.to_string();
+        assert!(error.ends_with("reads_leniently.txt:8:1: unknown key: foo"));
+
+        // Invalid entries are kept
+        let mut repo = Repo::open(&path, true).unwrap();
+        assert_eq!(repo.len(), 1);
+        assert_eq!(repo.errors().len(), 2);
+        assert!(repo.errors()[0]
+            .to_string()
+            .ends_with(":5:7: failed to parse year: invalid digit found in string"));
+        repo.add(with_id("Terminator", None, "t1")).ok();
+        repo.write().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "Alien
+year: 1979
+
+Aliens
+year: 86a
+
+Pearl
+foo: bar
+
+Terminator
+id: t1"
+        );
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn reads_and_writes_directory() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_directory");
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("Alien (1979).txt"), "Alien\nyear:1979").unwrap();
+        fs::write(path.join("Pearl.txt"), "Pearl\n").unwrap();
+        fs::write(path.join("notes.md"), "not an item").unwrap();
+
+        let mut repo = Repo::new(&path).unwrap();
+        assert_eq!(repo.kind(), Kind::Dir);
+        assert_eq!(repo.len(), 2);
+        assert_eq!(repo.get_by_index(0).name, "Alien");
+
+        // Only changed files are written
+        repo.add(media::Media::new("AC/DC", None)).ok();
+        let pearl = media::handle::Handle::from_user_input("Pearl");
+        repo.rename(&pearl, &media::handle::Handle::from_user_input("Pearl (2022)"))
+            .unwrap();
+        repo.write().unwrap();
+
+        let mut names: Vec<_> =
+            fs::read_dir(&path).unwrap().map(|e| e.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, ["AC_DC.txt", "Alien (1979).txt", "Pearl (2022).txt", "notes.md"]);
+        assert_eq!(fs::read_to_string(path.join("Alien (1979).txt")).unwrap(), "Alien\nyear:1979");
+        assert_eq!(
+            fs::read_to_string(path.join("Pearl (2022).txt")).unwrap(),
+            "Pearl\nyear: 2022\n"
+        );
+
+        // Items that would share a file
+        repo.add(media::Media::new("AC_DC", None)).ok();
+        assert!(repo.write().is_err());
+
+        fs::remove_dir_all(&path).ok();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn writes_single_items_to_sqlite() {
+        let path = std::env::temp_dir().join("mtracker_test_writes_single_items_to_sqlite.sqlite");
+        fs::remove_file(&path).ok();
+        let handle = media::handle::Handle::from_user_input;
+        let mut repo = Repo::new(&path).unwrap();
+        repo.add(media::Media::new("Alien", Some(1979))).unwrap();
+        repo.add(media::Media::new("Pearl", Some(2022))).unwrap();
+        repo.write().unwrap();
+
+        // Only changed items are written, so rows added since stay
+        let mut other = Repo::new(&path).unwrap();
+        other.add(media::Media::new("Dune", None)).unwrap();
+        other.write().unwrap();
+        repo.get(&handle("Alien (1979)")).unwrap().rating = Some(8);
+        repo.remove_by_handle(&handle("Pearl (2022)")).unwrap();
+        repo.write().unwrap();
+        let reopened = Repo::new(&path).unwrap();
+        let items: Vec<_> =
+            reopened.get_all().iter().map(|m| (m.name.as_str(), m.rating)).collect();
+        assert_eq!(items, [("Alien", Some(8)), ("Dune", None)]);
+
+        // Items without ID are written along with the others
+        storage::open(&path, Kind::Sqlite)
+            .unwrap()
+            .upsert(media::Media::new("Heat", None))
+            .unwrap();
+        let mut repo = Repo::new(&path).unwrap();
+        repo.get(&handle("Heat")).unwrap().rating = Some(9);
+        assert!(!repo.by_id(repo.changes.as_ref().unwrap()));
+        repo.write().unwrap();
+        assert_eq!(Repo::new(&path).unwrap().get_by_index(2).rating, Some(9));
+        fs::remove_file(&path).ok();
+    }
+
+    /// Tests that must pass with every kind of storage that can be written
+    macro_rules! repo_tests {
+        ($name:ident, $extension:literal) => {
+            mod $name {
+                use super::*;
+
+                // Path of a new database
+                fn db(test: &str) -> path::PathBuf {
+                    let mut path = std::env::temp_dir();
+                    path.push(format!("mtracker_test_{}_{test}.{}", stringify!($name), $extension));
+                    fs::remove_file(&path).ok();
+                    path
+                }
+
+                #[test]
+                fn writes_changes() {
+                    let path = db("writes_changes");
+                    let handle = media::handle::Handle::from_user_input;
+                    let mut repo = Repo::new(&path).unwrap();
+                    repo.add(media::Media::new("Alien", Some(1979))).unwrap();
+                    repo.add(media::Media::new("Aliens", Some(1986))).unwrap();
+                    repo.add(media::Media::new("Pearl", Some(2022))).unwrap();
+                    repo.write().unwrap();
+                    let reopened = || Repo::new(&path).unwrap();
+                    assert_eq!(reopened().get_all(), repo.get_all());
+
+                    // A few changes
+                    repo.get(&handle("Alien (1979)")).unwrap().rating = Some(8);
+                    repo.rename(&handle("Pearl (2022)"), &handle("Pearl")).unwrap();
+                    repo.remove_by_handle(&handle("Aliens (1986)")).unwrap();
+                    repo.add(media::Media::new("Aliens", Some(1986))).unwrap();
+                    repo.add(media::Media::new("X", Some(2022))).unwrap();
+                    repo.remove_by_handle(&handle("X (2022)")).unwrap();
+                    repo.get_by_index_mut(0).tags = vec!["horror".into()];
+                    repo.write().unwrap();
+                    assert_eq!(reopened().get_all(), repo.get_all());
+                    assert_eq!(reopened().get_by_index(2).name, "Aliens");
+
+                    // Many changes
+                    for i in 0..40 {
+                        repo.add(media::Media::new(&format!("Item {i}"), None)).unwrap();
+                    }
+                    repo.remove_by_index(0);
+                    repo.write().unwrap();
+                    assert_eq!(reopened().len(), 42);
+                    assert_eq!(reopened().get_all(), repo.get_all());
+
+                    // All items replaced
+                    repo.set_all(vec![media::Media::new("Dune", None)]);
+                    repo.write().unwrap();
+                    assert_eq!(reopened().get_all(), repo.get_all());
+                    fs::remove_file(&path).ok();
+                }
+
+                #[test]
+                fn looks_up_items() {
+                    let mut repo = Repo::new(&db("looks_up_items")).unwrap();
+                    repo.add(media::Media::new("Alien", Some(1979))).ok();
+                    repo.add(media::Media::new("Aliens", Some(1986))).ok();
+                    repo.add(media::Media::new("Pearl", Some(2022))).ok();
+                    repo.add(media::Media::new("Pearl", Some(1999))).ok();
+                    repo.add(media::Media::new("Pearl", None)).ok();
+
+                    let lookup = |input| repo.lookup(&media::handle::Handle::from_user_input(input));
+                    assert_eq!(lookup("Alien (1979)"), Lookup::Found(0));
+                    assert_eq!(lookup("alien"), Lookup::Found(0));
+                    assert_eq!(lookup("ALIENS (1986)"), Lookup::Found(1));
+                    assert_eq!(lookup("Pearl"), Lookup::Found(4));
+                    assert_eq!(lookup("pearl"), Lookup::Ambiguous(vec![2, 3, 4]));
+                    assert_eq!(lookup("pearl (1999)"), Lookup::Found(3));
+                    assert_eq!(lookup("ali"), Lookup::NotFound(vec![0, 1]));
+                    assert_eq!(lookup("alien (1980)"), Lookup::NotFound(vec![]));
+                    assert_eq!(lookup("Terminator"), Lookup::NotFound(vec![]));
+                }
+
+                #[test]
+                fn finds_aliased_tags() {
+                    tag::set_aliases(tag::Aliases::parse("mystery: whodunit").unwrap());
+                    let mut repo = Repo::new(&db("finds_aliased_tags")).unwrap();
+                    let mut knives_out = media::Media::new("Knives Out", Some(2019));
+                    knives_out.tags = vec!["whodunit".into()];
+                    repo.add(knives_out).unwrap();
+                    assert_eq!(repo.tagged("mystery"), [0]);
+                    assert_eq!(repo.tagged("whodunit"), [0]);
+                }
+
+                #[test]
+                fn keeps_index_up_to_date() {
+                    let handle = media::handle::Handle::from_user_input;
+                    let mut repo = Repo::new(&db("keeps_index_up_to_date")).unwrap();
+                    let mut alien = media::Media::new("Alien", Some(1979));
+                    alien.tags = vec!["horror/space".into(), "sci-fi".into()];
+                    repo.add(alien).ok();
+                    repo.add(media::Media::new("Pearl", Some(2022))).ok();
+                    assert!(repo.contains(&handle("Pearl (2022)")));
+                    assert_eq!(repo.tagged("horror"), [0]);
+
+                    // Maintained on add and remove
+                    let mut aliens = media::Media::new("Aliens", Some(1986));
+                    aliens.tags = vec!["horror".into()];
+                    repo.add(aliens).ok();
+                    assert_eq!(repo.tagged("horror"), [0, 2]);
+                    repo.remove_by_handle(&handle("Alien (1979)")).unwrap();
+                    assert_eq!(repo.tagged("horror"), [1]);
+                    assert!(repo.tagged("horror/space").is_empty());
+                    assert_eq!(repo.lookup(&handle("Aliens (1986)")), Lookup::Found(1));
+
+                    // Rebuilt after changes
+                    repo.get(&handle("Pearl (2022)")).unwrap().tags = vec!["horror/slasher".into()];
+                    assert_eq!(repo.tagged("horror"), [0, 1]);
+                    repo.rename(&handle("Pearl (2022)"), &handle("Pearl")).unwrap();
+                    assert!(!repo.contains(&handle("Pearl (2022)")));
+                    assert!(repo.contains(&handle("Pearl")));
+                }
+
+                #[test]
+                fn updates_changed_items_in_index() {
+                    let handle = media::handle::Handle::from_user_input;
+                    let mut repo = Repo::new(&db("updates_changed_items_in_index")).unwrap();
+                    for i in 0..80 {
+                        repo.add(with_id(&format!("Item {i}"), None, &format!("i{i}"))).unwrap();
+                    }
+                    assert_eq!(repo.lookup(&handle("Item 3")), Lookup::Found(3));
+
+                    // Changed items are added again, the others stay in place
+                    repo.get(&handle("Item 3")).unwrap().tags = vec!["horror".into()];
+                    repo.get_by_index_mut(1).tags = vec!["horror/slasher".into()];
+                    repo.get_by_index_mut(1).name = "Renamed".into();
+                    assert_eq!(repo.index.borrow().as_ref().unwrap().stale, [3, 1, 1]);
+                    assert_eq!(repo.tagged("horror"), [1, 3]);
+                    assert_eq!(repo.lookup(&handle("Renamed")), Lookup::Found(1));
+                    assert!(!repo.contains(&handle("Item 1")));
+                    assert_eq!(repo.lookup(&handle("#i1")), Lookup::Found(1));
+                    assert!(repo.index.borrow().as_ref().unwrap().stale.is_empty());
+
+                    // Removing moves changed items as well
+                    repo.get_by_index_mut(3).tags.clear();
+                    repo.remove_by_index(0);
+                    assert_eq!(repo.tagged("horror"), [0]);
+                    assert_eq!(repo.lookup(&handle("Item 3")), Lookup::Found(2));
+                    assert_eq!(repo.lookup(&handle("#i79")), Lookup::Found(78));
+                }
+
+                #[test]
+                fn renames_in_place() {
+                    let mut repo = Repo::new(&db("renames_in_place")).unwrap();
+                    repo.add(media::Media::new("Alien", None)).ok();
+                    repo.add(media::Media::new("Aliens", Some(1986))).ok();
+                    repo.add(media::Media::new("Pearl", Some(2022))).ok();
+
+                    let old = media::handle::Handle::from_user_input("Alien");
+                    let new = media::handle::Handle::from_user_input("Alien (1979)");
+                    repo.rename(&old, &new).unwrap();
+                    assert_eq!(repo.get_by_index(0).name, "Alien");
+                    assert_eq!(repo.get_by_index(0).year, Some(1979));
+
+                    // Collision with another item
+                    let new = media::handle::Handle::from_user_input("Pearl (2022)");
+                    let error = repo.rename(&old, &new).unwrap_err();
+                    assert!(matches!(error, Error::NotFound(h) if h == old));
+                    let old = media::handle::Handle::from_user_input("Alien (1979)");
+                    let error = repo.rename(&old, &new).unwrap_err();
+                    assert!(matches!(error, Error::AlreadyExists(h) if h == new));
+
+                    // Replace keeps position
+                    let aliens = media::handle::Handle::from_user_input("Aliens (1986)");
+                    let mut item = media::Media::new("Aliens", Some(1986));
+                    item.rating = Some(8);
+                    repo.replace(&aliens, item).unwrap();
+                    assert_eq!(repo.get_by_index(1).rating, Some(8));
+                    assert!(repo.replace(&aliens, media::Media::new("Alien", Some(1979))).is_err());
+                }
+
+                #[test]
+                fn finds_by_id() {
+                    let mut repo = Repo::new(&db("finds_by_id")).unwrap();
+                    repo.add(media::Media::new("Alien", Some(1979))).unwrap();
+                    repo.add(with_id("Pearl", Some(2022), "p1")).unwrap();
+
+                    // New items get a short, unique ID
+                    let id = repo.get_by_index(0).id.clone().unwrap();
+                    assert_eq!(id.len(), 4);
+                    assert!(media::is_valid_id(&id));
+                    let error = repo.add(with_id("Aliens", Some(1986), "p1")).unwrap_err();
+                    assert!(matches!(error, Error::Invalid { key, .. } if key == "id"));
+
+                    let by_id = |id: &str| media::handle::Handle::from_user_input(&format!("#{id}"));
+                    assert_eq!(repo.lookup(&by_id(&id)), Lookup::Found(0));
+                    assert_eq!(repo.lookup(&by_id("p1")), Lookup::Found(1));
+                    assert!(!repo.contains(&by_id("zzzz")));
+
+                    // Names that look like IDs
+                    repo.add(media::Media::new("#Alive", Some(2020))).unwrap();
+                    repo.add(media::Media::new("#p1", None)).unwrap();
+                    assert_eq!(repo.lookup(&by_id("Alive")), Lookup::Found(2));
+                    assert_eq!(repo.lookup(&by_id("p1")), Lookup::Found(1));
+                    repo.remove_by_index(3);
+                    repo.remove_by_index(2);
+
+                    // IDs stay the same through renames and edits
+                    let pearl = media::handle::Handle::from_user_input("Pearl (2022)");
+                    let new = media::handle::Handle::from_user_input("Pearl (2023)");
+                    repo.rename(&pearl, &new).unwrap();
+                    repo.replace(&by_id("p1"), media::Media::new("Pearl", Some(2022))).unwrap();
+                    assert_eq!(repo.get_by_index(1).id.as_deref(), Some("p1"));
+                    assert!(repo.replace(&by_id("p1"), with_id("Pearl", None, &id)).is_err());
+
+                    // Positions move on removal
+                    repo.remove_by_index(0);
+                    assert_eq!(repo.lookup(&by_id("p1")), Lookup::Found(0));
+                    assert!(!repo.contains(&by_id(&id)));
+                }
+            }
+        };
+    }
+
+    repo_tests!(text, "txt");
+    #[cfg(feature = "sqlite")]
+    repo_tests!(sqlite, "sqlite");
 }
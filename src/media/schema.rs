@@ -0,0 +1,183 @@
+use std::{fs, path};
+
+use crate::{
+    error::{Error, InvalidEntry, Result},
+    media::parser::{ParseError, ParseErrorKind},
+};
+
+/// Version of the text format written by this version of mtracker. Files
+/// without a header have version 1.
+pub const VERSION: u32 = 1;
+
+/// Start of the optional first line of a text database, e.g.:
+/// "# mtracker-format: 1"
+pub const HEADER: &str = "# mtracker-format:";
+
+/// Upgrade of the text format from version `from` to the next one
+pub struct Migration {
+    pub from: u32,
+    /// Gets the content without header and returns the upgraded one
+    pub upgrade: fn(&str) -> String,
+}
+
+/// Migrations of older formats, in order
+pub const MIGRATIONS: &[Migration] = &[];
+
+pub fn header(version: u32) -> String {
+    format!("{HEADER} {version}")
+}
+
+/// Version of a text database and the length of its header, including the
+/// line break. Errors point at the version.
+pub fn read_header(content: &str) -> Result<(u32, usize), ParseError> {
+    let line = content.split_inclusive('\n').next().unwrap_or_default();
+    let Some(value) = line.strip_prefix(HEADER) else {
+        return Ok((1, 0));
+    };
+
+    let column = HEADER.len() + value.len() - value.trim_start().len() + 1;
+    let version = value.trim().parse().map_err(|e: std::num::ParseIntError| {
+        let kind = ParseErrorKind::InvalidValue {
+            key: "mtracker-format".into(),
+            message: e.to_string(),
+        };
+        ParseError::new(1, column, kind)
+    })?;
+    Ok((version, line.len()))
+}
+
+/// Bring the content of a text database to the current version. If it had to
+/// be upgraded, the file is rewritten and the original kept as a backup, e.g.
+/// "db.txt.v1.bak". Files of newer versions are refused.
+pub fn upgrade(path: &path::Path, content: String) -> Result<String> {
+    upgrade_to(path, content, VERSION, MIGRATIONS)
+}
+
+fn upgrade_to(
+    path: &path::Path,
+    content: String,
+    current: u32,
+    migrations: &[Migration],
+) -> Result<String> {
+    let Some((version, upgraded)) = migrate_to(path, &content, current, migrations)? else {
+        return Ok(content);
+    };
+    backup(path, version)?;
+    fs::write(path, &upgraded)?;
+    Ok(upgraded)
+}
+
+/// Upgrade the content of a text database without writing it. Returns the
+/// version it had and the upgraded content, or `None` if it is up to date.
+pub fn migrate(path: &path::Path, content: &str) -> Result<Option<(u32, String)>> {
+    migrate_to(path, content, VERSION, MIGRATIONS)
+}
+
+fn migrate_to(
+    path: &path::Path,
+    content: &str,
+    current: u32,
+    migrations: &[Migration],
+) -> Result<Option<(u32, String)>> {
+    let (version, header_len) = read_header(content).map_err(|error| {
+        Error::InvalidEntries(vec![InvalidEntry {
+            path: path.to_path_buf(),
+            error,
+        }])
+    })?;
+    if version > current {
+        return Err(Error::NewerFormat {
+            path: path.to_path_buf(),
+            version,
+        });
+    }
+    if version == current {
+        return Ok(None);
+    }
+
+    let mut body = content[header_len..].to_string();
+    for from in version..current {
+        let Some(migration) = migrations.iter().find(|m| m.from == from) else {
+            return Err(Error::invalid(
+                "mtracker-format",
+                format!("{}: unknown format version {version}", path.to_string_lossy()),
+            ));
+        };
+        body = (migration.upgrade)(&body);
+    }
+    Ok(Some((version, format!("{}\n{body}", header(current)))))
+}
+
+/// Keep a copy of a database before upgrading it, e.g. "db.txt.v1.bak"
+pub fn backup(path: &path::Path, version: u32) -> Result<()> {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(format!(".v{version}.bak"));
+    fs::copy(path, &backup)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_header() {
+        assert_eq!(read_header("Alien\nyear: 1979"), Ok((1, 0)));
+        assert_eq!(read_header(""), Ok((1, 0)));
+        assert_eq!(read_header("# mtracker-format: 3\nAlien"), Ok((3, 21)));
+        assert_eq!(read_header("# mtracker-format:2"), Ok((2, 19)));
+
+        let error = read_header("# mtracker-format: x\nAlien").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 20));
+        assert!(
+            matches!(error.kind, ParseErrorKind::InvalidValue { key, .. } if key == "mtracker-format")
+        );
+    }
+
+    #[test]
+    fn upgrades() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_upgrades.txt");
+        let backup = path.with_extension("txt.v1.bak");
+        fs::remove_file(&backup).ok();
+
+        // Version 1 to 2 renames "seen" to "last_seen", 2 to 3 changes ratings of 0 to 1
+        let migrations = [
+            Migration {
+                from: 2,
+                upgrade: |body| body.replace("\nrating: 0", "\nrating: 1"),
+            },
+            Migration {
+                from: 1,
+                upgrade: |body| body.replace("\nseen:", "\nlast_seen:"),
+            },
+        ];
+        let upgrade = |content: &str, current| {
+            fs::write(&path, content).unwrap();
+            upgrade_to(&path, content.into(), current, &migrations)
+        };
+
+        // Up to date
+        let content = "Alien\nseen: 2024-01-01";
+        assert_eq!(upgrade(content, 1).unwrap(), content);
+        assert!(!backup.exists());
+
+        // Two versions, in order
+        let upgraded = upgrade("Alien\nrating: 0\nseen: 2024-01-01", 3).unwrap();
+        assert_eq!(upgraded, "# mtracker-format: 3\nAlien\nrating: 1\nlast_seen: 2024-01-01");
+        assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "Alien\nrating: 0\nseen: 2024-01-01");
+
+        // Newer than supported
+        let error = upgrade("# mtracker-format: 4\nAlien", 3).unwrap_err();
+        assert!(matches!(error, Error::NewerFormat { version: 4, .. }));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# mtracker-format: 4\nAlien");
+
+        // Unknown versions
+        assert!(matches!(upgrade("# mtracker-format: 0\n", 3), Err(Error::Invalid { .. })));
+        assert!(matches!(upgrade("# mtracker-format: x\n", 3), Err(Error::InvalidEntries(_))));
+
+        fs::remove_file(&path).ok();
+        fs::remove_file(&backup).ok();
+    }
+}
@@ -0,0 +1,321 @@
+use std::{fs, io::Read, path};
+
+use anyhow::Result;
+
+use crate::{
+    error::InvalidEntry,
+    media::{handle::Handle, Media},
+};
+
+pub mod dir;
+pub mod encrypted;
+pub mod serialized;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod text;
+
+/// Items read from storage, along with errors of entries that couldn't be read
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub items: Vec<Media>,
+    pub errors: Vec<InvalidEntry>,
+}
+
+/// Where items are kept, e.g. a text file. The single-item methods read and
+/// write everything by default; storage that can do better overrides them.
+pub trait Storage {
+    /// Read all items. Invalid entries are skipped and reported as errors.
+    fn load(&mut self) -> Result<Loaded>;
+
+    /// Write all items, replacing the stored ones
+    fn save(&mut self, items: &[Media]) -> Result<()>;
+
+    /// Write all items in their canonical form, also unchanged ones, e.g. to
+    /// fix their formatting
+    fn rewrite(&mut self, items: &[Media]) -> Result<()> {
+        self.save(items)
+    }
+
+    /// Read the item with the given handle, or ID (see `Handle::id`)
+    fn get(&mut self, handle: &Handle) -> Result<Option<Media>> {
+        let mut items = self.load()?.items;
+        Ok(position(&items, handle).map(|i| items.swap_remove(i)))
+    }
+
+    /// Replace the item with the same ID, or the same handle if it has none,
+    /// or add it at the end
+    fn upsert(&mut self, item: Media) -> Result<()> {
+        let mut items = self.load()?.items;
+        let same = |m: &Media| match &item.id {
+            Some(id) => m.id.as_ref() == Some(id),
+            None => m.matches_handle(&item.handle()),
+        };
+        match items.iter().position(same) {
+            Some(i) => items[i] = item,
+            None => items.push(item),
+        }
+        self.save(&items)
+    }
+
+    /// Remove the item with the given handle or ID. Returns whether it existed.
+    fn remove(&mut self, handle: &Handle) -> Result<bool> {
+        let mut items = self.load()?.items;
+        let Some(i) = position(&items, handle) else {
+            return Ok(false);
+        };
+        items.remove(i);
+        self.save(&items)?;
+        Ok(true)
+    }
+
+    fn query(&mut self, filter: &dyn Fn(&Media) -> bool) -> Result<Vec<Media>> {
+        Ok(self.load()?.items.into_iter().filter(|m| filter(m)).collect())
+    }
+}
+
+// Position of the item with the given handle, by ID first like `Repo::lookup`
+fn position(items: &[Media], handle: &Handle) -> Option<usize> {
+    let by_id = handle
+        .id()
+        .and_then(|id| items.iter().position(|m| m.id.as_deref() == Some(id)));
+    by_id.or_else(|| items.iter().position(|m| m.matches_handle(handle)))
+}
+
+/// Kind of storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Kind {
+    /// A single text file
+    Text,
+    /// A directory with one text file per item
+    Dir,
+    /// An SQLite database
+    Sqlite,
+    Json,
+    Yaml,
+    Toml,
+    /// A text file, encrypted with a passphrase
+    Encrypted,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Kind::Text),
+            "dir" => Some(Kind::Dir),
+            "sqlite" => Some(Kind::Sqlite),
+            "json" => Some(Kind::Json),
+            "yaml" => Some(Kind::Yaml),
+            "toml" => Some(Kind::Toml),
+            "encrypted" => Some(Kind::Encrypted),
+            _ => None,
+        }
+    }
+
+    /// File extension of a new database, empty for directories
+    pub fn extension(self) -> &'static str {
+        match self {
+            Kind::Text => "txt",
+            Kind::Dir => "",
+            Kind::Sqlite => "sqlite",
+            Kind::Json => "json",
+            Kind::Yaml => "yaml",
+            Kind::Toml => "toml",
+            Kind::Encrypted => "enc",
+        }
+    }
+
+    fn from_extension(path: &path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "sqlite" | "sqlite3" => Some(Kind::Sqlite),
+            "json" => Some(Kind::Json),
+            "yaml" | "yml" => Some(Kind::Yaml),
+            "toml" => Some(Kind::Toml),
+            "enc" => Some(Kind::Encrypted),
+            _ => None,
+        }
+    }
+
+    /// Kind of an existing database, or of a new one by its file extension
+    pub fn detect(path: &path::Path) -> Self {
+        if path.is_dir() {
+            return Kind::Dir;
+        }
+
+        let mut header = [0; 16];
+        match fs::File::open(path) {
+            Ok(mut file) => match file.read_exact(&mut header) {
+                Ok(()) if &header == b"SQLite format 3\0" => Kind::Sqlite,
+                Ok(()) if &header == encrypted::MAGIC => Kind::Encrypted,
+                // Existing files are only SQLite or encrypted databases with the header
+                _ => Self::from_extension(path)
+                    .filter(|k| !matches!(k, Kind::Sqlite | Kind::Encrypted))
+                    .unwrap_or(Kind::Text),
+            },
+            Err(_) => Self::from_extension(path).unwrap_or(Kind::Text),
+        }
+    }
+}
+
+/// Open storage of the given kind. Nothing is read until it is used.
+pub fn open(path: &path::Path, kind: Kind) -> Result<Box<dyn Storage>> {
+    match kind {
+        Kind::Text => Ok(Box::new(text::TextStorage::new(path))),
+        Kind::Dir => Ok(Box::new(dir::DirStorage::new(path))),
+        #[cfg(feature = "sqlite")]
+        Kind::Sqlite => Ok(Box::new(sqlite::SqliteStorage::new(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        Kind::Sqlite => Err(anyhow::anyhow!("mtracker was built without SQLite support")),
+        Kind::Encrypted => Ok(Box::new(encrypted::EncryptedStorage::new(path))),
+        Kind::Json | Kind::Yaml | Kind::Toml => {
+            Ok(Box::new(serialized::SerializedStorage::new(path, kind)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that every kind of storage must pass
+    macro_rules! storage_tests {
+        ($name:ident, $kind:expr) => {
+            mod $name {
+                use super::*;
+
+                fn storage(test: &str) -> (path::PathBuf, Box<dyn Storage>) {
+                    let mut path = std::env::temp_dir();
+                    path.push(format!("mtracker_test_{}_{test}", stringify!($name)));
+                    fs::remove_file(&path).ok();
+                    fs::remove_dir_all(&path).ok();
+                    if $kind == Kind::Encrypted {
+                        crate::media::storage::encrypted::set_passphrase(&path, "secret");
+                    }
+                    let storage = open(&path, $kind).unwrap();
+                    (path, storage)
+                }
+
+                fn cleanup(path: &path::Path) {
+                    fs::remove_file(path).ok();
+                    fs::remove_dir_all(path).ok();
+                }
+
+                fn items() -> Vec<Media> {
+                    let mut pearl = Media::new("Pearl", Some(2022));
+                    pearl.rating = Some(8);
+                    pearl.tags = vec!["horror".into(), "watchlist".into()];
+                    pearl.note = "Recommended by Max".into();
+                    pearl.last_seen = chrono::NaiveDate::from_ymd_opt(2024, 6, 12);
+                    vec![Media::new("Alien", Some(1979)), pearl, Media::new("Whiplash", None)]
+                }
+
+                #[test]
+                fn loads_nothing() {
+                    let (path, mut storage) = storage("loads_nothing");
+                    assert!(storage.load().unwrap().items.is_empty());
+                    cleanup(&path);
+                }
+
+                #[test]
+                fn saves_and_loads() {
+                    let (path, mut storage) = storage("saves_and_loads");
+                    storage.save(&items()).unwrap();
+                    let loaded = storage.load().unwrap();
+                    assert!(loaded.errors.is_empty());
+
+                    // Directories are sorted by file name
+                    let mut loaded = loaded.items;
+                    if $kind == Kind::Dir {
+                        loaded.sort_by(|a, b| a.name.cmp(&b.name));
+                    }
+                    assert_eq!(loaded, items());
+
+                    // Again, from scratch
+                    let mut reopened = open(&path, $kind).unwrap();
+                    assert_eq!(reopened.load().unwrap().items.len(), 3);
+
+                    // Fewer items
+                    storage.save(&items()[1..]).unwrap();
+                    let loaded = open(&path, $kind).unwrap().load().unwrap().items;
+                    assert_eq!(loaded.len(), 2);
+                    assert!(loaded.iter().all(|m| m.name != "Alien"));
+                    cleanup(&path);
+                }
+
+                #[test]
+                fn gets_upserts_and_removes() {
+                    let (path, mut storage) = storage("gets_upserts_and_removes");
+                    storage.save(&items()).unwrap();
+
+                    let pearl = Handle::from_user_input("Pearl (2022)");
+                    assert_eq!(storage.get(&pearl).unwrap(), Some(items().remove(1)));
+                    assert_eq!(storage.get(&Handle::from_user_input("Pearl")).unwrap(), None);
+
+                    let mut item = Media::new("Pearl", Some(2022));
+                    item.rating = Some(9);
+                    storage.upsert(item).unwrap();
+                    storage.upsert(Media::new("Dune", None)).unwrap();
+                    assert_eq!(storage.get(&pearl).unwrap().unwrap().rating, Some(9));
+                    assert_eq!(storage.load().unwrap().items.len(), 4);
+
+                    // By ID, also after renaming
+                    let mut dune = Media::new("Dune", Some(2021));
+                    dune.id = Some("d1".into());
+                    storage.upsert(dune.clone()).unwrap();
+                    dune.name = "Dune: Part One".into();
+                    storage.upsert(dune.clone()).unwrap();
+                    let by_id = Handle::from_user_input("#d1");
+                    assert_eq!(storage.get(&by_id).unwrap(), Some(dune));
+                    assert!(storage.remove(&by_id).unwrap());
+
+                    assert!(storage.remove(&pearl).unwrap());
+                    assert!(!storage.remove(&pearl).unwrap());
+                    assert_eq!(storage.get(&pearl).unwrap(), None);
+                    let loaded = open(&path, $kind).unwrap().load().unwrap().items;
+                    assert_eq!(loaded.len(), 3);
+                    assert!(loaded.iter().any(|m| m.name == "Dune"));
+                    cleanup(&path);
+                }
+
+                #[test]
+                fn queries() {
+                    let (path, mut storage) = storage("queries");
+                    storage.save(&items()).unwrap();
+                    let rated = storage.query(&|m| m.rating.is_some()).unwrap();
+                    assert_eq!(rated, vec![items().remove(1)]);
+                    cleanup(&path);
+                }
+            }
+        };
+    }
+
+    storage_tests!(text, Kind::Text);
+    storage_tests!(dir, Kind::Dir);
+    #[cfg(feature = "sqlite")]
+    storage_tests!(sqlite, Kind::Sqlite);
+    storage_tests!(json, Kind::Json);
+    storage_tests!(yaml, Kind::Yaml);
+    storage_tests!(toml, Kind::Toml);
+    storage_tests!(encrypted, Kind::Encrypted);
+
+    #[test]
+    fn detects_kind() {
+        let dir = std::env::temp_dir();
+        assert_eq!(Kind::detect(&dir), Kind::Dir);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.txt")), Kind::Text);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.sqlite")), Kind::Sqlite);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.json")), Kind::Json);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.yml")), Kind::Yaml);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.toml")), Kind::Toml);
+        assert_eq!(Kind::detect(&dir.join("mtracker_test_new.enc")), Kind::Encrypted);
+
+        // Existing files by their content
+        let path = dir.join("mtracker_test_detects_kind.enc");
+        fs::write(&path, "Alien").unwrap();
+        assert_eq!(Kind::detect(&path), Kind::Text);
+        fs::write(&path, [&super::encrypted::MAGIC[..], b"..."].concat()).unwrap();
+        assert_eq!(Kind::detect(&path.with_extension("txt")), Kind::Text);
+        assert_eq!(Kind::detect(&path), Kind::Encrypted);
+        fs::remove_file(&path).ok();
+    }
+}
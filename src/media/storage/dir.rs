@@ -0,0 +1,92 @@
+use std::{collections::HashMap, fs, path};
+
+use anyhow::{anyhow, Result};
+
+use super::{Loaded, Storage};
+use crate::{
+    error::InvalidEntry,
+    media::{parser, Media},
+};
+
+/// A directory with one text file per item, e.g. "Alien (1979).txt"
+pub struct DirStorage {
+    path: path::PathBuf,
+    // Entries as last read or written, by file name, to only write changes
+    files: HashMap<String, String>,
+}
+
+impl DirStorage {
+    pub fn new(path: &path::Path) -> Self {
+        DirStorage {
+            path: path.to_path_buf(),
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl Storage for DirStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let mut loaded = Loaded::default();
+        self.files.clear();
+        if !self.path.exists() {
+            return Ok(loaded);
+        }
+
+        let mut names: Vec<String> = fs::read_dir(&self.path)?
+            .filter_map(|e| e.ok()?.file_name().into_string().ok())
+            .filter(|n| n.ends_with(".txt"))
+            .collect();
+        names.sort();
+
+        for name in names {
+            let path = self.path.join(&name);
+            match parser::parse_single(&fs::read_to_string(&path)?) {
+                Ok(item) => {
+                    self.files.insert(name, item.to_db_entry());
+                    loaded.items.push(item);
+                }
+                // Invalid files are left alone when saving
+                Err(error) => loaded.errors.push(InvalidEntry { path, error }),
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    fn save(&mut self, items: &[Media]) -> Result<()> {
+        let mut files = HashMap::new();
+        for item in items {
+            if files.insert(file_name(item), item.to_db_entry()).is_some() {
+                return Err(anyhow!("file name used by several items: {}", file_name(item)));
+            }
+        }
+
+        // Don't overwrite files that weren't read, e.g. invalid ones
+        for name in files.keys().filter(|n| !self.files.contains_key(*n)) {
+            if self.path.join(name).exists() {
+                return Err(anyhow!(
+                    "file already exists: {}",
+                    self.path.join(name).to_string_lossy()
+                ));
+            }
+        }
+
+        fs::create_dir_all(&self.path)?;
+        for (name, entry) in &files {
+            if self.files.get(name) != Some(entry) {
+                fs::write(self.path.join(name), format!("{entry}\n"))?;
+            }
+        }
+        for name in self.files.keys().filter(|n| !files.contains_key(*n)) {
+            fs::remove_file(self.path.join(name))?;
+        }
+
+        self.files = files;
+        Ok(())
+    }
+}
+
+/// File name of an item: "Alien (1979).txt"
+fn file_name(item: &Media) -> String {
+    format!("{}.txt", item.handle().to_string().replace(['/', '\0'], "_"))
+}
@@ -0,0 +1,359 @@
+use std::{
+    fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use zeroize::Zeroizing;
+
+use super::{Loaded, Storage};
+use crate::{
+    error::{Error, InvalidEntry},
+    media::{
+        parser::{self, ParseError, ParseErrorKind},
+        schema, Media,
+    },
+};
+
+/// Start of every encrypted database
+pub const MAGIC: &[u8; 16] = b"mtracker-crypt1\n";
+
+/// Environment variable with the passphrase, instead of asking for it
+pub const PASSPHRASE_VAR: &str = "MTRACKER_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+// Magic, Argon2 memory cost, iterations and parallelism, salt
+const HEADER_LEN: usize = MAGIC.len() + 3 * 4 + SALT_LEN;
+// Highest Argon2 memory cost (in KiB), iterations and parallelism accepted when
+// reading, as the header isn't authenticated before deriving the key
+const MAX_M_COST: u32 = 1 << 20;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+/// Key derived from a passphrase, along with what is needed to derive it again
+pub struct Secret {
+    params: Params,
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl Secret {
+    /// Derive a key with a new salt
+    pub fn new(passphrase: &str) -> Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, new_params(), salt)
+    }
+
+    fn derive(passphrase: &str, params: Params, salt: [u8; SALT_LEN]) -> Result<Self> {
+        let mut key = Zeroizing::new([0; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| anyhow!("failed to derive key: {e}"))?;
+        Ok(Secret { params, salt, key })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        for n in [self.params.m_cost(), self.params.t_cost(), self.params.p_cost()] {
+            header.extend(n.to_le_bytes());
+        }
+        header.extend(self.salt);
+        header
+    }
+
+    /// Encrypt text with a new nonce. The header is authenticated as well.
+    pub fn encrypt(&self, text: &str) -> Result<Vec<u8>> {
+        let cipher = XChaCha20Poly1305::new(self.key.as_ref().into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let header = self.header();
+        let payload = Payload {
+            msg: text.as_bytes(),
+            aad: &header,
+        };
+        let ciphertext =
+            cipher.encrypt(&nonce, payload).map_err(|_| anyhow!("failed to encrypt"))?;
+        Ok([header, nonce.to_vec(), ciphertext].concat())
+    }
+
+    /// Decrypt data that was encrypted with a key from the same passphrase.
+    /// Returns the text and the key, to encrypt it again without deriving it.
+    /// Fails with `Error::Corrupt` for a wrong passphrase or damaged data.
+    pub fn decrypt(
+        path: &Path,
+        data: &[u8],
+        passphrase: &str,
+    ) -> Result<(Zeroizing<String>, Self)> {
+        let corrupt = |message: &str| Error::Corrupt {
+            path: path.to_path_buf(),
+            message: message.into(),
+        };
+        let damaged = || corrupt("damaged encrypted database");
+        if data.len() < HEADER_LEN + NONCE_LEN || !data.starts_with(MAGIC) {
+            return Err(damaged().into());
+        }
+
+        let number = |i: usize| {
+            let start = MAGIC.len() + 4 * i;
+            u32::from_le_bytes(data[start..start + 4].try_into().unwrap())
+        };
+        let (m_cost, t_cost, p_cost) = (number(0), number(1), number(2));
+        if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+            return Err(damaged().into());
+        }
+        let params = Params::new(m_cost, t_cost, p_cost, None).map_err(|_| damaged())?;
+        let salt = data[HEADER_LEN - SALT_LEN..HEADER_LEN].try_into().unwrap();
+        let secret = Self::derive(passphrase, params, salt)?;
+
+        let cipher = XChaCha20Poly1305::new(secret.key.as_ref().into());
+        let nonce = XNonce::from_slice(&data[HEADER_LEN..HEADER_LEN + NONCE_LEN]);
+        let payload = Payload {
+            msg: &data[HEADER_LEN + NONCE_LEN..],
+            aad: &data[..HEADER_LEN],
+        };
+        let text = cipher
+            .decrypt(nonce, payload)
+            .map_err(|_| corrupt("wrong passphrase, or the database is damaged"))?;
+        let text = String::from_utf8(text).map_err(|_| damaged())?;
+        Ok((Zeroizing::new(text), secret))
+    }
+}
+
+// Deriving keys is slow on purpose, but not in tests
+fn new_params() -> Params {
+    if cfg!(test) {
+        Params::new(Params::MIN_M_COST, 1, 1, None).unwrap()
+    } else {
+        Params::default()
+    }
+}
+
+// Passphrases entered so far, so that each database is only asked for once
+static PASSPHRASES: Mutex<Vec<(PathBuf, Zeroizing<String>)>> = Mutex::new(Vec::new());
+
+/// Passphrase of a database from MTRACKER_PASSPHRASE, or entered by the user.
+/// New passphrases have to be entered twice.
+pub fn passphrase(path: &Path, new: bool) -> Result<Zeroizing<String>> {
+    if let Some(passphrase) = std::env::var(PASSPHRASE_VAR).ok().filter(|p| !p.is_empty()) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    let mut known = PASSPHRASES.lock().unwrap();
+    if let Some((_, passphrase)) = known.iter().find(|(p, _)| p == path) {
+        return Ok(passphrase.clone());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "{} is encrypted. Set {PASSPHRASE_VAR} to read it without a terminal.",
+            path.to_string_lossy()
+        ));
+    }
+
+    let passphrase = prompt(&format!("Passphrase for {}: ", path.to_string_lossy()))?;
+    if passphrase.is_empty() {
+        return Err(anyhow!("passphrase can't be empty"));
+    }
+    if new && *prompt("Repeat passphrase: ")? != *passphrase {
+        return Err(anyhow!("passphrases don't match"));
+    }
+    known.push((path.to_path_buf(), passphrase.clone()));
+    Ok(passphrase)
+}
+
+/// Use a passphrase for an encrypted database, instead of asking for it
+pub fn set_passphrase(path: &Path, passphrase: &str) {
+    let mut known = PASSPHRASES.lock().unwrap();
+    known.retain(|(p, _)| p != path);
+    known.push((path.to_path_buf(), Zeroizing::new(passphrase.into())));
+}
+
+fn forget(path: &Path) {
+    PASSPHRASES.lock().unwrap().retain(|(p, _)| p != path);
+}
+
+// Ask on the terminal, also while the TUI has it in raw mode
+fn prompt(text: &str) -> Result<Zeroizing<String>> {
+    let raw = crossterm::terminal::is_raw_mode_enabled()?;
+    if raw {
+        crossterm::terminal::disable_raw_mode()?;
+    }
+    let input = rpassword::prompt_password(text).map(Zeroizing::new);
+    if raw {
+        crossterm::terminal::enable_raw_mode()?;
+    }
+    Ok(input?)
+}
+
+/// Replace a file, so that it is never half written
+pub fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// A text database, encrypted with a key derived from a passphrase. It is only
+/// decrypted in memory. Includes are not supported.
+pub struct EncryptedStorage {
+    path: PathBuf,
+    // Derived when reading, or when writing a new database
+    secret: Option<Secret>,
+    // Header with the format version, if any
+    header: Option<String>,
+    // Entries that failed to parse, kept as they are
+    invalid: Vec<Zeroizing<String>>,
+}
+
+impl EncryptedStorage {
+    pub fn new(path: &Path) -> Self {
+        EncryptedStorage {
+            path: path.to_path_buf(),
+            secret: None,
+            header: None,
+            invalid: vec![],
+        }
+    }
+}
+
+impl Storage for EncryptedStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let Ok(data) = fs::read(&self.path) else {
+            return Ok(Loaded::default());
+        };
+        let passphrase = passphrase(&self.path, false)?;
+        let (mut content, secret) =
+            Secret::decrypt(&self.path, &data, &passphrase).inspect_err(|_| {
+                forget(&self.path);
+            })?;
+        self.secret = Some(secret);
+
+        let upgraded = schema::migrate(&self.path, &content)?;
+        if let Some((version, text)) = &upgraded {
+            schema::backup(&self.path, *version)?;
+            content = Zeroizing::new(text.clone());
+        }
+
+        let mut loaded = Loaded::default();
+        self.header = content
+            .lines()
+            .next()
+            .filter(|l| l.starts_with(schema::HEADER))
+            .map(String::from);
+        self.invalid.clear();
+        for block in parser::blocks(&content) {
+            let result = match block.includes() {
+                Some(_) => {
+                    let kind =
+                        ParseErrorKind::InvalidInclude("not supported when encrypted".into());
+                    Err(ParseError::new(block.line, 1, kind))
+                }
+                None => block.parse(),
+            };
+            match result {
+                Ok(item) => loaded.items.push(item),
+                Err(error) => {
+                    loaded.errors.push(InvalidEntry {
+                        path: self.path.clone(),
+                        error,
+                    });
+                    self.invalid.push(Zeroizing::new(block.text.to_string()));
+                }
+            }
+        }
+
+        if upgraded.is_some() {
+            self.save(&loaded.items)?;
+        }
+        Ok(loaded)
+    }
+
+    fn save(&mut self, items: &[Media]) -> Result<()> {
+        if self.secret.is_none() {
+            self.secret = Some(Secret::new(&passphrase(&self.path, true)?)?);
+        }
+
+        let mut content = Zeroizing::new(String::new());
+        if let Some(header) = &self.header {
+            content.push_str(header);
+            content.push('\n');
+        }
+        let entries = items.iter().map(|m| Zeroizing::new(m.to_db_entry()));
+        for (i, entry) in entries.chain(self.invalid.iter().cloned()).enumerate() {
+            if i > 0 {
+                content.push_str("\n\n");
+            }
+            content.push_str(&entry);
+        }
+
+        write(&self.path, &self.secret.as_ref().unwrap().encrypt(&content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypts_and_decrypts() {
+        let path = Path::new("db.enc");
+        let secret = Secret::new("correct horse").unwrap();
+        let data = secret.encrypt("Alien\nnote: private").unwrap();
+        assert!(data.starts_with(MAGIC));
+        assert!(!data.windows(7).any(|w| w == b"private"));
+
+        let (text, _) = Secret::decrypt(path, &data, "correct horse").unwrap();
+        assert_eq!(*text, "Alien\nnote: private");
+        let error = Secret::decrypt(path, &data, "wrong horse").err().unwrap();
+        assert!(matches!(error.downcast_ref(), Some(Error::Corrupt { .. })));
+
+        // The header is authenticated too
+        let mut tampered = data.clone();
+        tampered[MAGIC.len() + 4] ^= 1;
+        assert!(Secret::decrypt(path, &tampered, "correct horse").is_err());
+        assert!(Secret::decrypt(path, &data[..HEADER_LEN], "correct horse").is_err());
+
+        // Costs that are too high to derive a key are refused right away
+        for (i, cost) in [0xFFFF_FFF0u32, 1 << 30, 1 << 30].into_iter().enumerate() {
+            let mut damaged = data.clone();
+            let start = MAGIC.len() + 4 * i;
+            damaged[start..start + 4].copy_from_slice(&cost.to_le_bytes());
+            let error = Secret::decrypt(path, &damaged, "correct horse").err().unwrap();
+            assert_eq!(error.to_string(), "db.enc: damaged encrypted database");
+        }
+
+        // A new nonce every time
+        assert_ne!(secret.encrypt("Alien").unwrap(), secret.encrypt("Alien").unwrap());
+    }
+
+    #[test]
+    fn keeps_invalid_entries() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_encrypted_keeps_invalid_entries.txt");
+        set_passphrase(&path, "secret");
+        let content = "# mtracker-format: 1\nAlien\n\nPearl\nfoo: bar\n\nDune";
+        write(&path, &Secret::new("secret").unwrap().encrypt(content).unwrap()).unwrap();
+
+        let mut storage = EncryptedStorage::new(&path);
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.items.len(), 2);
+        assert_eq!(loaded.errors[0].error.line, 5);
+
+        storage.save(&loaded.items[1..]).unwrap();
+        let (text, _) = Secret::decrypt(&path, &fs::read(&path).unwrap(), "secret").unwrap();
+        assert_eq!(*text, "# mtracker-format: 1\nDune\n\nPearl\nfoo: bar");
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,128 @@
+use std::{fs, io, path};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{Kind, Loaded, Storage};
+use crate::{
+    error::Error,
+    media::{parser, Media},
+};
+
+/// All items in one JSON, YAML or TOML file
+pub struct SerializedStorage {
+    path: path::PathBuf,
+    kind: Kind,
+}
+
+// TOML needs a table at the top level: [[items]]
+#[derive(Default, Deserialize, Serialize)]
+struct Document {
+    #[serde(default)]
+    items: Vec<Media>,
+}
+
+impl SerializedStorage {
+    pub fn new(path: &path::Path, kind: Kind) -> Self {
+        SerializedStorage {
+            path: path.to_path_buf(),
+            kind,
+        }
+    }
+}
+
+impl Storage for SerializedStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        // A missing file is an empty database, which is created when saving
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Loaded::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let at = |e: &dyn std::fmt::Display| Error::Corrupt {
+            path: self.path.clone(),
+            message: e.to_string(),
+        };
+
+        let items: Vec<Media> = match self.kind {
+            Kind::Json => serde_json::from_str(&content).map_err(|e| at(&e))?,
+            Kind::Yaml => serde_norway::from_str::<Option<_>>(&content)
+                .map_err(|e| at(&e))?
+                .unwrap_or_default(),
+            Kind::Toml => toml::from_str::<Document>(&content).map_err(|e| at(&e))?.items,
+            _ => unreachable!(),
+        };
+
+        // Items must be valid in the text format as well, e.g. notes are single lines
+        for (i, item) in items.iter().enumerate() {
+            match parser::parse_single(&item.to_db_entry()) {
+                Ok(parsed) if parsed == *item => {}
+                Ok(_) => {
+                    return Err(
+                        at(&format!("item {}: leading or trailing whitespace", i + 1)).into()
+                    )
+                }
+                Err(e) => return Err(at(&format!("item {}: {e}", i + 1)).into()),
+            }
+        }
+
+        Ok(Loaded {
+            items,
+            errors: vec![],
+        })
+    }
+
+    fn save(&mut self, items: &[Media]) -> Result<()> {
+        let content = match self.kind {
+            Kind::Json => serde_json::to_string_pretty(items)? + "\n",
+            Kind::Yaml => serde_norway::to_string(items)?,
+            Kind::Toml => toml::to_string(&Document {
+                items: items.to_vec(),
+            })?,
+            _ => unreachable!(),
+        };
+
+        fs::create_dir_all(self.path.parent().unwrap())?;
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_items() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_validates_items.json");
+        let mut storage = SerializedStorage::new(&path, Kind::Json);
+
+        let check = |storage: &mut SerializedStorage, content: &str| {
+            fs::write(&path, content).unwrap();
+            storage.load().map(|l| l.items.len()).map_err(|e| {
+                assert!(matches!(e.downcast_ref(), Some(Error::Corrupt { .. })));
+                e.to_string()
+            })
+        };
+        assert_eq!(check(&mut storage, r#"[{"name": "Alien", "year": 1979}]"#), Ok(1));
+        assert_eq!(check(&mut storage, "[]"), Ok(0));
+
+        let error = check(&mut storage, r#"[{"name": "Alien"}, {"name": "Pearl", "foo": 1}]"#);
+        assert!(error.unwrap_err().contains("unknown field `foo`"));
+        let error = check(&mut storage, r#"[{"name": "Alien", "note": "a\nb"}]"#);
+        assert!(error.unwrap_err().ends_with("item 1: delimiter missing: b"));
+        let error = check(&mut storage, r#"[{"name": "Alien", "tags": ["a", ""]}]"#);
+        assert!(error.unwrap_err().ends_with("item 1: empty tag"));
+        let error = check(&mut storage, r#"[{"name": " Alien"}]"#);
+        assert!(error.unwrap_err().ends_with("item 1: leading or trailing whitespace"));
+
+        // Only a missing file is empty
+        fs::write(&path, b"[\xff]").unwrap();
+        assert!(storage.load().is_err());
+        fs::remove_file(&path).ok();
+        assert!(storage.load().unwrap().items.is_empty());
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,219 @@
+use std::{fs, path};
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Params};
+
+use super::{Loaded, Storage};
+use crate::media::{handle::Handle, Media};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS items (
+    position INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    year INTEGER,
+    rating INTEGER,
+    tags TEXT NOT NULL,
+    note TEXT NOT NULL,
+    last_seen TEXT,
+    id TEXT
+);
+CREATE INDEX IF NOT EXISTS items_name ON items (name, year);";
+
+const COLUMNS: &str = "name, year, rating, tags, note, last_seen, id";
+
+/// An SQLite database. Items are kept in the order of the `position` column.
+pub struct SqliteStorage {
+    conn: Connection,
+    // Entries as last read or written, by position, to only write changes.
+    // `None` if unknown, so that everything is written.
+    rows: Option<Vec<String>>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: &path::Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+
+        // Databases created before items had IDs
+        let has_id = conn
+            .prepare("SELECT 1 FROM pragma_table_info('items') WHERE name = 'id'")?
+            .exists([])?;
+        if !has_id {
+            conn.execute("ALTER TABLE items ADD COLUMN id TEXT", [])?;
+        }
+        Ok(SqliteStorage { conn, rows: None })
+    }
+
+    // Position of the first row that matches a condition
+    fn position_where(&self, condition: &str, params: impl Params) -> Result<Option<i64>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT position FROM items WHERE {condition} ORDER BY position LIMIT 1"),
+                params,
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Position of the item with the given handle, by ID first like `Repo::lookup`
+    fn position(&self, handle: &Handle) -> Result<Option<i64>> {
+        if let Some(position) = handle.id().map(|id| self.position_where("id = ?1", [id])) {
+            if let Some(position) = position? {
+                return Ok(Some(position));
+            }
+        }
+        self.position_where("name = ?1 AND year IS ?2", params![handle.name, handle.year])
+    }
+}
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Media> {
+    let tags: String = row.get(3)?;
+    let last_seen = match row.get::<_, Option<String>>(5)? {
+        Some(date) => Some(chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, Box::new(e))
+        })?),
+        None => None,
+    };
+
+    Ok(Media {
+        name: row.get(0)?,
+        id: row.get(6)?,
+        year: row.get(1)?,
+        rating: row.get(2)?,
+        tags: tags.split(',').filter(|t| !t.is_empty()).map(String::from).collect(),
+        note: row.get(4)?,
+        last_seen,
+        origin: None,
+    })
+}
+
+fn insert(conn: &Connection, position: i64, item: &Media) -> Result<()> {
+    conn.prepare_cached(&format!(
+        "INSERT OR REPLACE INTO items (position, {COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+    ))?
+    .execute(params![
+        position,
+        item.name,
+        item.year,
+        item.rating,
+        item.tags.join(","),
+        item.note,
+        item.last_seen.map(|d| d.to_string()),
+        item.id,
+    ])?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let mut statement = self
+            .conn
+            .prepare(&format!("SELECT {COLUMNS}, position FROM items ORDER BY position"))?;
+        let mut loaded = Loaded::default();
+        let mut contiguous = true;
+        let rows = statement.query_map([], |row| Ok((from_row(row)?, row.get::<_, i64>(7)?)))?;
+        for (i, row) in rows.enumerate() {
+            let (item, position) = row?;
+            contiguous &= position == i as i64;
+            loaded.items.push(item);
+        }
+
+        self.rows = contiguous.then(|| loaded.items.iter().map(Media::to_db_entry).collect());
+        Ok(loaded)
+    }
+
+    fn save(&mut self, items: &[Media]) -> Result<()> {
+        let entries: Vec<String> = items.iter().map(Media::to_db_entry).collect();
+
+        let tx = self.conn.transaction()?;
+        if self.rows.is_none() {
+            tx.execute("DELETE FROM items", [])?;
+        }
+        for (i, item) in items.iter().enumerate() {
+            let unchanged = self.rows.as_ref().and_then(|r| r.get(i)) == Some(&entries[i]);
+            if !unchanged {
+                insert(&tx, i as i64, item)?;
+            }
+        }
+        tx.execute("DELETE FROM items WHERE position >= ?1", [items.len() as i64])?;
+        tx.commit()?;
+
+        self.rows = Some(entries);
+        Ok(())
+    }
+    fn get(&mut self, handle: &Handle) -> Result<Option<Media>> {
+        let Some(position) = self.position(handle)? else {
+            return Ok(None);
+        };
+        Ok(Some(self.conn.query_row(
+            &format!("SELECT {COLUMNS} FROM items WHERE position = ?1"),
+            [position],
+            from_row,
+        )?))
+    }
+
+    fn upsert(&mut self, item: Media) -> Result<()> {
+        let existing = match &item.id {
+            Some(id) => self.position_where("id = ?1", [id])?,
+            None => {
+                self.position_where("name = ?1 AND year IS ?2", params![item.name, item.year])?
+            }
+        };
+        let position = match existing {
+            Some(p) => p,
+            None => self.conn.query_row(
+                "SELECT COALESCE(MAX(position) + 1, 0) FROM items",
+                [],
+                |row| row.get(0),
+            )?,
+        };
+        insert(&self.conn, position, &item)?;
+
+        // Rows are numbered from 0 while the cache is known
+        let entry = item.to_db_entry();
+        match self.rows.as_mut() {
+            Some(rows) if (position as usize) < rows.len() => rows[position as usize] = entry,
+            Some(rows) if position as usize == rows.len() => rows.push(entry),
+            _ => self.rows = None,
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, handle: &Handle) -> Result<bool> {
+        let Some(position) = self.position(handle)? else {
+            return Ok(false);
+        };
+
+        // Close the gap. Negate positions first to keep them unique.
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM items WHERE position = ?1", [position])?;
+        tx.execute("UPDATE items SET position = -position WHERE position > ?1", [position])?;
+        tx.execute("UPDATE items SET position = -position - 1 WHERE position < 0", [])?;
+        tx.commit()?;
+
+        match self.rows.as_mut() {
+            Some(rows) if (position as usize) < rows.len() => {
+                rows.remove(position as usize);
+            }
+            _ => self.rows = None,
+        }
+        Ok(true)
+    }
+
+    fn query(&mut self, filter: &dyn Fn(&Media) -> bool) -> Result<Vec<Media>> {
+        let mut statement =
+            self.conn.prepare(&format!("SELECT {COLUMNS} FROM items ORDER BY position"))?;
+        let mut items = Vec::new();
+        for item in statement.query_map([], from_row)? {
+            let item = item?;
+            if filter(&item) {
+                items.push(item);
+            }
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,636 @@
+use std::{
+    fs,
+    io::{Seek, SeekFrom, Write},
+    ops::Range,
+    path::{self, PathBuf},
+};
+
+use anyhow::Result;
+
+use super::{Loaded, Storage};
+use crate::{
+    error::InvalidEntry,
+    media::{
+        parser::{self, ParseError, ParseErrorKind},
+        schema, Media,
+    },
+};
+
+const SEPARATOR: &str = "\n\n";
+
+/// All items in one text file, separated by blank lines, and the files it
+/// includes. Only changed parts of the files are written.
+pub struct TextStorage {
+    path: path::PathBuf,
+    // Origin of the items in this file: `None` for the database itself, which
+    // also gets new items
+    origin: Option<PathBuf>,
+    // File content as last read or written
+    content: String,
+    // Byte ranges in `content` and the items as last read or written
+    items: Vec<(Range<usize>, Media)>,
+    // Byte ranges in `content` of blocks that are kept as they are: entries that
+    // failed to parse and includes
+    kept: Vec<Range<usize>>,
+    // Storage of all included files, also the ones included by those
+    included: Vec<TextStorage>,
+}
+
+/// Files that were read so far, to detect cycles and files included twice
+#[derive(Default)]
+struct Visited {
+    // The file being read and the ones that include it
+    chain: Vec<PathBuf>,
+    all: Vec<PathBuf>,
+}
+
+/// A change of the file content
+struct Splice {
+    // Byte range in the file content that is replaced
+    range: Range<usize>,
+    replacement: String,
+    // Indices of the new items that are in the replacement
+    changed: Range<usize>,
+}
+
+impl TextStorage {
+    pub fn new(path: &path::Path) -> Self {
+        TextStorage {
+            path: path.to_path_buf(),
+            origin: None,
+            content: String::new(),
+            items: vec![],
+            kept: vec![],
+            included: vec![],
+        }
+    }
+
+    fn included(path: &path::Path) -> Self {
+        TextStorage {
+            origin: Some(path.to_path_buf()),
+            ..Self::new(path)
+        }
+    }
+
+    /// Find the change to the file content from the items as last read or
+    /// written to `items`. Returns `None` if the whole file has to be written.
+    fn splice(&self, items: &[Media]) -> Option<Splice> {
+        let old = &self.items;
+
+        // Unchanged items at the start and the end
+        let prefix = old.iter().zip(items).take_while(|(o, n)| o.1 == **n).count();
+        let max_suffix = old.len().min(items.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(items.iter().rev())
+            .take(max_suffix)
+            .take_while(|(o, n)| o.1 == **n)
+            .count();
+
+        let changed = prefix..items.len() - suffix;
+        let old_changed = &old[prefix..old.len() - suffix];
+        let entries: Vec<String> = items[changed.clone()].iter().map(Media::to_db_entry).collect();
+        let entries = entries.join(SEPARATOR);
+
+        let (range, replacement) = match (old_changed, entries.is_empty()) {
+            // Nothing changed
+            ([], true) => (0..0, entries),
+
+            // Replace items
+            ([first, .., last] | [first @ last], false) => (first.0.start..last.0.end, entries),
+
+            // Remove items, along with the blank lines after them, or before them at the end
+            ([first, .., last] | [first @ last], true) => {
+                let after = &self.content[last.0.end..];
+                if after.trim().is_empty() {
+                    (self.content[..first.0.start].trim_end().len()..last.0.end, entries)
+                } else {
+                    (first.0.start..self.content.len() - after.trim_start().len(), entries)
+                }
+            }
+
+            // Insert items before the next one, or append them to the file
+            ([], false) => match old.get(prefix) {
+                Some(next) => (next.0.start..next.0.start, entries + SEPARATOR),
+                None => match self.content.trim_end().len() {
+                    0 => return None,
+                    end => (end..end, format!("{SEPARATOR}{entries}")),
+                },
+            },
+        };
+
+        // Invalid entries are only kept by a full rewrite
+        if self.kept.iter().any(|r| r.start < range.end && r.end > range.start) {
+            return None;
+        }
+
+        Some(Splice {
+            range,
+            replacement,
+            changed,
+        })
+    }
+
+    // Write only the changed part of the file
+    fn write_part(&mut self, items: &[Media], splice: Splice) -> Result<()> {
+        let Splice {
+            range,
+            replacement,
+            changed,
+        } = splice;
+        if range.is_empty() && replacement.is_empty() {
+            return Ok(());
+        }
+
+        let mut content = String::with_capacity(self.content.len() + replacement.len());
+        content += &self.content[..range.start];
+        content += &replacement;
+        content += &self.content[range.end..];
+
+        // Items after the change only need to be written if they moved
+        let mut file = fs::OpenOptions::new().write(true).open(&self.path)?;
+        file.seek(SeekFrom::Start(range.start as u64))?;
+        if content.len() == self.content.len() {
+            file.write_all(replacement.as_bytes())?;
+        } else {
+            file.write_all(&content.as_bytes()[range.start..])?;
+            file.set_len(content.len() as u64)?;
+        }
+
+        // Update ranges
+        let shift = |r: &Range<usize>| match r.start >= range.end {
+            true => {
+                r.start + content.len() - self.content.len()
+                    ..r.end + content.len() - self.content.len()
+            }
+            false => r.clone(),
+        };
+        let suffix = items.len() - changed.end;
+        let mut ranges: Vec<(Range<usize>, Media)> = self.items.drain(..changed.start).collect();
+        let mut offset = range.start + replacement.len() - replacement.trim_start().len();
+        for item in &items[changed] {
+            let len = item.to_db_entry().len();
+            ranges.push((offset..offset + len, item.clone()));
+            offset += len + SEPARATOR.len();
+        }
+        let old_suffix = self.items.len() - suffix;
+        ranges.extend(self.items.drain(old_suffix..).map(|(r, m)| (shift(&r), m)));
+
+        self.kept = self.kept.iter().map(shift).collect();
+        self.items = ranges;
+        self.content = content;
+        Ok(())
+    }
+
+    // Write the whole file, keeping invalid entries and includes near their original position
+    fn write_all(&mut self, items: &[Media]) -> Result<()> {
+        // Create path if it doesn't exist
+        fs::create_dir_all(self.path.parent().unwrap())?;
+
+        let kept: Vec<(usize, &str)> = self
+            .kept
+            .iter()
+            .map(|r| {
+                let position = self.items.iter().filter(|(i, _)| i.start < r.start).count();
+                (position, &self.content[r.clone()])
+            })
+            .collect();
+
+        // Keep the header with the format version
+        let mut content = match self.content.lines().next() {
+            Some(header) if header.starts_with(schema::HEADER) => format!("{header}\n"),
+            _ => String::new(),
+        };
+        let mut ranges = vec![];
+        let mut kept_ranges = vec![];
+        let mut kept = kept.into_iter().peekable();
+        for (i, item) in items.iter().enumerate() {
+            while let Some((_, block)) = kept.next_if(|(pos, _)| *pos <= i) {
+                kept_ranges.push(content.len()..content.len() + block.len());
+                content += block;
+                content += SEPARATOR;
+            }
+            let entry = item.to_db_entry();
+            ranges.push((content.len()..content.len() + entry.len(), item.clone()));
+            content += &entry;
+            content += SEPARATOR;
+        }
+        for (_, block) in kept {
+            kept_ranges.push(content.len()..content.len() + block.len());
+            content += block;
+            content += SEPARATOR;
+        }
+        content.truncate(content.trim_end().len());
+
+        fs::write(&self.path, &content)?;
+        self.content = content;
+        self.items = ranges;
+        self.kept = kept_ranges;
+        Ok(())
+    }
+
+    // Read the file and the ones it includes. Returns the storage of those.
+    fn load_file(&mut self, visited: &mut Visited) -> Result<(Loaded, Vec<TextStorage>)> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => schema::upgrade(&self.path, content)?,
+            Err(_) => String::new(),
+        };
+        let canonical = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        visited.chain.push(canonical.clone());
+        visited.all.push(canonical);
+
+        let mut loaded = Loaded::default();
+        let mut included = vec![];
+        self.items.clear();
+        self.kept.clear();
+        for block in parser::blocks(&content) {
+            let range = block.offset..block.offset + block.text.len();
+            let includes = match block.includes() {
+                Some(includes) => includes,
+                None => match block.parse() {
+                    Ok(mut item) => {
+                        item.origin.clone_from(&self.origin);
+                        self.items.push((range, item.clone()));
+                        loaded.items.push(item);
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            self.kept.push(range);
+
+            let includes = match includes {
+                Ok(includes) => includes,
+                Err(e) => {
+                    loaded.errors.push(self.invalid_entry(e));
+                    continue;
+                }
+            };
+            for (line, pattern) in includes {
+                let error = |message: String| {
+                    let kind = ParseErrorKind::InvalidInclude(message);
+                    self.invalid_entry(ParseError::new(line, 1, kind))
+                };
+                let paths = match self.resolve(pattern) {
+                    Ok(paths) => paths,
+                    Err(message) => {
+                        loaded.errors.push(error(message));
+                        continue;
+                    }
+                };
+
+                for path in paths {
+                    let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                    if let Some(start) = visited.chain.iter().position(|p| *p == canonical) {
+                        let cycle: Vec<_> = visited.chain[start..]
+                            .iter()
+                            .chain([&canonical])
+                            .map(|p| p.to_string_lossy())
+                            .collect();
+                        loaded.errors.push(error(format!("cycle: {}", cycle.join(" -> "))));
+                        continue;
+                    }
+                    if visited.all.contains(&canonical) {
+                        let message = format!("included twice: {}", path.to_string_lossy());
+                        loaded.errors.push(error(message));
+                        continue;
+                    }
+
+                    let mut file = TextStorage::included(&path);
+                    let (file_loaded, nested) = file.load_file(visited)?;
+                    loaded.items.extend(file_loaded.items);
+                    loaded.errors.extend(file_loaded.errors);
+                    included.push(file);
+                    included.extend(nested);
+                }
+            }
+        }
+
+        visited.chain.pop();
+        self.content = content;
+        Ok((loaded, included))
+    }
+
+    fn invalid_entry(&self, error: ParseError) -> InvalidEntry {
+        InvalidEntry {
+            path: self.path.clone(),
+            error,
+        }
+    }
+
+    // Files matching an include, relative to this file. Paths without wildcards
+    // must exist.
+    fn resolve(&self, pattern: &str) -> Result<Vec<PathBuf>, String> {
+        let path = self.path.parent().unwrap_or(path::Path::new("")).join(pattern);
+        if !pattern.contains(['*', '?', '[']) {
+            return match path.is_file() {
+                true => Ok(vec![path]),
+                false => Err(format!("file not found: {pattern}")),
+            };
+        }
+
+        let paths = glob::glob(&path.to_string_lossy()).map_err(|e| format!("{pattern}: {e}"))?;
+        Ok(paths.filter_map(Result::ok).filter(|p| p.is_file()).collect())
+    }
+
+    // Write the items of this file, without the included ones. With `full`,
+    // unchanged items are written as well.
+    fn save_file(&mut self, items: &[Media], full: bool) -> Result<()> {
+        if full {
+            return self.write_all(items);
+        }
+
+        // Fall back to writing everything if the file was changed by someone else
+        let unchanged = fs::read(&self.path).is_ok_and(|c| c == self.content.as_bytes());
+        match self.splice(items) {
+            Some(splice) if unchanged => self.write_part(items, splice),
+            _ => self.write_all(items),
+        }
+    }
+
+    fn save_all(&mut self, items: &[Media], full: bool) -> Result<()> {
+        if self.included.is_empty() {
+            return self.save_file(items, full);
+        }
+
+        // Each item goes back to its file. Items of files that are no longer
+        // included go to the database itself.
+        let mut parts = vec![vec![]; self.included.len() + 1];
+        for item in items {
+            let file = self.included.iter().position(|f| f.origin == item.origin);
+            parts[file.map_or(0, |i| i + 1)].push(item.clone());
+        }
+
+        let mut parts = parts.into_iter();
+        self.save_file(&parts.next().unwrap(), full)?;
+        for (file, items) in self.included.iter_mut().zip(parts) {
+            file.save_file(&items, full)?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for TextStorage {
+    fn load(&mut self) -> Result<Loaded> {
+        let (loaded, included) = self.load_file(&mut Visited::default())?;
+        self.included = included;
+        Ok(loaded)
+    }
+
+    fn save(&mut self, items: &[Media]) -> Result<()> {
+        self.save_all(items, false)
+    }
+
+    fn rewrite(&mut self, items: &[Media]) -> Result<()> {
+        self.save_all(items, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(test: &str, content: &str) -> (path::PathBuf, TextStorage, Vec<Media>) {
+        let mut path = std::env::temp_dir();
+        path.push(format!("mtracker_test_text_{test}.txt"));
+        fs::write(&path, content).unwrap();
+        let mut storage = TextStorage::new(&path);
+        let items = storage.load().unwrap().items;
+        (path, storage, items)
+    }
+
+    // Save items and check that the file is as expected, also after reading it again
+    fn assert_saves(storage: &mut TextStorage, items: &[Media], expected: &str) {
+        storage.save(items).unwrap();
+        assert_eq!(fs::read_to_string(&storage.path).unwrap(), expected);
+        assert_eq!(storage.content, expected);
+        let mut reloaded = TextStorage::new(&storage.path);
+        assert_eq!(reloaded.load().unwrap().items, items);
+        assert_eq!(reloaded.items, storage.items);
+        assert_eq!(reloaded.kept, storage.kept);
+    }
+
+    #[test]
+    fn writes_changes_only() {
+        let (path, mut storage, mut items) = storage(
+            "writes_changes_only",
+            "Alien\nyear:1979\n\n\nPearl\nyear:  2022\n\nDune\nrating:8\n",
+        );
+
+        // Other items keep their formatting
+        items[1].rating = Some(7);
+        let expected = "Alien\nyear:1979\n\n\nPearl\nyear: 2022\nrating: 7\n\nDune\nrating:8\n";
+        assert_saves(&mut storage, &items, expected);
+
+        // Same length
+        items[1].rating = Some(8);
+        let expected = "Alien\nyear:1979\n\n\nPearl\nyear: 2022\nrating: 8\n\nDune\nrating:8\n";
+        assert_saves(&mut storage, &items, expected);
+
+        // Append
+        items.push(Media::new("Whiplash", None));
+        let expected =
+            "Alien\nyear:1979\n\n\nPearl\nyear: 2022\nrating: 8\n\nDune\nrating:8\n\nWhiplash\n";
+        assert_saves(&mut storage, &items, expected);
+
+        // Insert
+        items.insert(1, Media::new("Aliens", None));
+        let expected =
+            "Alien\nyear:1979\n\n\nAliens\n\nPearl\nyear: 2022\nrating: 8\n\nDune\nrating:8\n\nWhiplash\n";
+        assert_saves(&mut storage, &items, expected);
+
+        // Remove in the middle, at the end and at the start
+        items.remove(2);
+        let expected = "Alien\nyear:1979\n\n\nAliens\n\nDune\nrating:8\n\nWhiplash\n";
+        assert_saves(&mut storage, &items, expected);
+        items.pop();
+        let expected = "Alien\nyear:1979\n\n\nAliens\n\nDune\nrating:8\n";
+        assert_saves(&mut storage, &items, expected);
+        items.remove(0);
+        let expected = "Aliens\n\nDune\nrating:8\n";
+        assert_saves(&mut storage, &items, expected);
+
+        // Remove everything, then start over
+        items.clear();
+        assert_saves(&mut storage, &items, "\n");
+        items.push(Media::new("Alien", None));
+        assert_saves(&mut storage, &items, "Alien");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn writes_all_if_changed_outside() {
+        let (path, mut storage, mut items) =
+            storage("writes_all_if_changed_outside", "Alien\nrating: 7\n\nPearl");
+
+        // Same length as before
+        fs::write(&path, "Aliens\nrating:7\n\nPearl").unwrap();
+        items[1].rating = Some(5);
+        assert_saves(&mut storage, &items, "Alien\nrating: 7\n\nPearl\nrating: 5");
+
+        // Formatting of unchanged items
+        fs::write(&path, "Alien\nrating: 7 \n\nPearl\nrating: 5").unwrap();
+        let mut storage = TextStorage::new(&path);
+        let items = storage.load().unwrap().items;
+        storage.save(&items).unwrap();
+        assert_eq!(storage.content, "Alien\nrating: 7 \n\nPearl\nrating: 5");
+        storage.rewrite(&items).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Alien\nrating: 7\n\nPearl\nrating: 5");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn keeps_invalid_entries() {
+        let (path, mut storage, mut items) =
+            storage("keeps_invalid_entries", "Alien\n\nAliens\nfoo: bar\n\nPearl");
+
+        items[1].rating = Some(7);
+        assert_saves(&mut storage, &items, "Alien\n\nAliens\nfoo: bar\n\nPearl\nrating: 7");
+
+        // Full rewrite, as the invalid entry is between changed items
+        items[0].rating = Some(8);
+        items[1].rating = Some(8);
+        let expected = "Alien\nrating: 8\n\nAliens\nfoo: bar\n\nPearl\nrating: 8";
+        assert_saves(&mut storage, &items, expected);
+
+        items.remove(0);
+        assert_saves(&mut storage, &items, "Aliens\nfoo: bar\n\nPearl\nrating: 8");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn keeps_header() {
+        let (path, mut storage, mut items) =
+            storage("keeps_header", "# mtracker-format: 1\nAlien\n\nPearl");
+        assert_eq!(items.len(), 2);
+
+        items.remove(0);
+        assert_saves(&mut storage, &items, "# mtracker-format: 1\nPearl");
+        items.push(Media::new("Dune", None));
+        assert_saves(&mut storage, &items, "# mtracker-format: 1\nPearl\n\nDune");
+        storage.content.clear();
+        assert_saves(&mut storage, &items, "Pearl\n\nDune");
+
+        fs::write(&path, "# mtracker-format: 1\n").unwrap();
+        let mut storage = TextStorage::new(&path);
+        assert!(storage.load().unwrap().items.is_empty());
+        assert_saves(&mut storage, &[Media::new("Alien", None)], "# mtracker-format: 1\n\nAlien\n");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn follows_includes() {
+        let dir = std::env::temp_dir().join("mtracker_test_follows_includes");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("books")).unwrap();
+        fs::write(dir.join("movies.txt"), "Pearl\nyear: 2022").unwrap();
+        fs::write(dir.join("books/b.txt"), "Foundation").unwrap();
+        fs::write(dir.join("books/a.txt"), "Dune").unwrap();
+        let content = "include: movies.txt\ninclude: books/*.txt\n\nAlien";
+        fs::write(dir.join("db.txt"), content).unwrap();
+        let mut storage = TextStorage::new(&dir.join("db.txt"));
+        let mut items = storage.load().unwrap().items;
+
+        let names: Vec<_> = items.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Pearl", "Dune", "Foundation", "Alien"]);
+        assert_eq!(items[0].origin, Some(dir.join("movies.txt")));
+        assert_eq!(items[2].origin, Some(dir.join("books/b.txt")));
+        assert_eq!(items[3].origin, None);
+
+        // Items go back to their files, new ones to the database itself
+        items[0].rating = Some(7);
+        items.remove(1);
+        items.push(Media::new("Whiplash", None));
+        storage.save(&items).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("movies.txt"), "Pearl\nyear: 2022\nrating: 7");
+        assert_eq!(read("books/a.txt"), "");
+        assert_eq!(read("books/b.txt"), "Foundation");
+        assert_eq!(read("db.txt"), format!("{content}\n\nWhiplash"));
+
+        let reloaded = TextStorage::new(&dir.join("db.txt")).load().unwrap().items;
+        assert_eq!(reloaded, items);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn reports_invalid_includes() {
+        let dir = std::env::temp_dir().join("mtracker_test_reports_invalid_includes");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "include: b.txt\n\nPearl").unwrap();
+        fs::write(dir.join("b.txt"), "include: a.txt\ninclude: c.txt\n\nDune").unwrap();
+        fs::write(dir.join("c.txt"), "Alien").unwrap();
+        let db = "include: a.txt\ninclude: c.txt\ninclude: missing.txt\ninclude: *.md";
+        fs::write(dir.join("db.txt"), db).unwrap();
+
+        let dir = fs::canonicalize(&dir).unwrap();
+        let loaded = TextStorage::new(&dir.join("db.txt")).load().unwrap();
+        let names: Vec<_> = loaded.items.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Alien", "Dune", "Pearl"]);
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let errors: Vec<_> = loaded.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                format!(
+                    "{}:1:1: invalid include: cycle: {} -> {} -> {}",
+                    path("b.txt"),
+                    path("a.txt"),
+                    path("b.txt"),
+                    path("a.txt")
+                ),
+                format!(
+                    "{}:2:1: invalid include: included twice: {}",
+                    path("db.txt"),
+                    path("c.txt")
+                ),
+                format!("{}:3:1: invalid include: file not found: missing.txt", path("db.txt")),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn rewrites_when_changed_by_someone_else() {
+        let (path, mut storage, mut items) = storage("rewrites_when_changed", "Alien\n\nPearl");
+        fs::write(&path, "Alien\n\nPearl\n\nDune").unwrap();
+        items[1].rating = Some(7);
+        assert_saves(&mut storage, &items, "Alien\n\nPearl\nrating: 7");
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with: cargo test --release -- --ignored --nocapture"]
+    fn benchmark() {
+        let content: Vec<String> = (0..50_000)
+            .map(|i| format!("Movie {i}\nyear: {}\nrating: {}\ntags: a, b", 1900 + i % 125, i % 10))
+            .collect();
+        let (path, mut storage, mut items) = storage("benchmark", &content.join("\n\n"));
+
+        let time = |storage: &mut TextStorage, items: &[Media], name: &str| {
+            let start = std::time::Instant::now();
+            storage.save(items).unwrap();
+            eprintln!("{name}: {:?}", start.elapsed());
+        };
+
+        items[25_000].rating = Some(1);
+        time(&mut storage, &items, "change one item");
+        items.push(Media::new("New", None));
+        time(&mut storage, &items, "append one item");
+        items.remove(0);
+        time(&mut storage, &items, "remove first item");
+        storage.content.clear();
+        time(&mut storage, &items, "full rewrite");
+
+        fs::remove_file(&path).ok();
+    }
+}
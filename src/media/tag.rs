@@ -0,0 +1,114 @@
+use std::{collections::HashMap, fs, path::PathBuf, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use clap::crate_name;
+
+/// Separates parent and child in hierarchical tags, e.g.: "horror/slasher"
+pub const SEPARATOR: char = '/';
+
+static ALIASES: OnceLock<Aliases> = OnceLock::new();
+
+/// Maps alternative spellings of tags to their canonical form
+#[derive(Debug, Default)]
+pub struct Aliases(HashMap<String, String>);
+
+impl Aliases {
+    /// Parse aliases from lines like "sci-fi: scifi, science-fiction"
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut map = HashMap::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((canonical, aliases)) = line.split_once(':') else {
+                return Err(anyhow!("delimiter missing in line {}: {line}", i + 1));
+            };
+            for alias in aliases.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                map.insert(alias.to_string(), canonical.trim().to_string());
+            }
+        }
+        Ok(Aliases(map))
+    }
+
+    /// Read aliases from the user's config directory, if the file exists
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(Self::path()) {
+            Ok(content) => Self::parse(&content),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        let mut path = dirs::config_dir().unwrap_or_default();
+        path.push(format!("{}/aliases.txt", crate_name!()));
+        path
+    }
+
+    pub fn normalize<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.0.get(tag).map_or(tag, String::as_str)
+    }
+}
+
+/// Set the aliases used by `normalize`. Can only be done once.
+pub fn set_aliases(aliases: Aliases) {
+    ALIASES.set(aliases).ok();
+}
+
+/// Get the canonical form of a tag
+pub fn normalize(tag: &str) -> &str {
+    match ALIASES.get() {
+        Some(aliases) => aliases.normalize(tag),
+        None => tag,
+    }
+}
+
+/// Whether `tag` is `ancestor` itself or one of its descendants
+pub fn is_within(tag: &str, ancestor: &str) -> bool {
+    match tag.strip_prefix(ancestor) {
+        Some(rest) => rest.is_empty() || rest.starts_with(SEPARATOR),
+        None => false,
+    }
+}
+
+/// All levels of a hierarchical tag: "a/b/c" -> ["a", "a/b", "a/b/c"]
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices(SEPARATOR).map(|(i, _)| &tag[..i]).chain(std::iter::once(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aliases() {
+        let aliases = Aliases::parse(
+            "# comment
+sci-fi: scifi, science-fiction
+
+horror:",
+        )
+        .unwrap();
+        assert_eq!(aliases.normalize("scifi"), "sci-fi");
+        assert_eq!(aliases.normalize("science-fiction"), "sci-fi");
+        assert_eq!(aliases.normalize("sci-fi"), "sci-fi");
+        assert_eq!(aliases.normalize("horror"), "horror");
+
+        assert!(Aliases::parse("sci-fi scifi").is_err());
+    }
+
+    #[test]
+    fn matches_hierarchy() {
+        assert!(is_within("horror", "horror"));
+        assert!(is_within("horror/slasher", "horror"));
+        assert!(is_within("horror/slasher/80s", "horror/slasher"));
+        assert!(!is_within("horrorcomedy", "horror"));
+        assert!(!is_within("horror", "horror/slasher"));
+    }
+
+    #[test]
+    fn lists_ancestors() {
+        assert_eq!(ancestors("a/b/c").collect::<Vec<_>>(), ["a", "a/b", "a/b/c"]);
+        assert_eq!(ancestors("a").collect::<Vec<_>>(), ["a"]);
+    }
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{arg_util, args, convert, media::storage::Kind};
+
+pub fn command() -> Command {
+    Command::new("migrate")
+        .about("Copy the database to another kind of storage")
+        .long_about(format!(
+            "Copy the database to another kind of storage
+
+{}
+
+The current database is left as it is. Set 'db' in the config to use the new one.",
+            args::KIND_HELP
+        ))
+        .arg_required_else_help(true)
+        .arg(args::kind().required(true).help("Kind of storage to copy to"))
+        .arg(
+            Arg::new("TARGET")
+                .required(false)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Path of the new database [default: the current one with another extension]"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let repo = arg_util::repo_from_matches(matches)?;
+    let kind = Kind::from_name(matches.get_one::<String>("TO").unwrap()).unwrap();
+
+    let target = match matches.get_one::<PathBuf>("TARGET") {
+        Some(path) => path.clone(),
+        None => repo.path.with_extension(kind.extension()),
+    };
+    convert::copy(&repo, &target, kind)?;
+
+    println!("Copied {} item(s) to: {}", repo.len(), target.to_string_lossy());
+    if !repo.errors().is_empty() {
+        println!("{} invalid entries were skipped.", repo.errors().len());
+    }
+    println!("To use it, set db = \"{}\" in the config.", target.to_string_lossy());
+    Ok(())
+}
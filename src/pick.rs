@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    arg_util, args, config, list,
+    media::{self, Media},
+};
+
+pub fn command() -> Command {
+    Command::new("pick")
+        .about("Pick random items (from the watchlist by default)")
+        .after_long_help(args::TERM_HELP)
+        .arg_required_else_help(false)
+        .arg(args::term().help("Terms to search for (tag, year), defaults to watchlist"))
+        .arg(
+            Arg::new("COUNT")
+                .required(false)
+                .short('n')
+                .long("count")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("Number of items to pick"),
+        )
+        .arg(
+            Arg::new("BY_AGE")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("by-age")
+                .help("Prefer items that have been in the database longer"),
+        )
+        .arg(args::tag().long("prefer").short('P').help("Prefer items with these tags"))
+        .arg(
+            Arg::new("SEED")
+                .required(false)
+                .long("seed")
+                .value_parser(clap::value_parser!(u64))
+                .help("Seed for the random number generator"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let repo = arg_util::repo_from_matches(matches)?;
+    let items = repo.get_all();
+    let max_rating = repo.max_rating();
+
+    let mut terms = arg_util::terms_from_matches(matches);
+    if terms.is_empty() {
+        terms.push(&config::get().watchlist_tag);
+    }
+
+    let by_age = *matches.get_one::<bool>("BY_AGE").unwrap_or(&false);
+    let preferred = arg_util::tags_from_matches(matches);
+
+    // Items appear in the order they were added, so the position is their age
+    let candidates: Vec<(&Media, f64)> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| list::matches_terms(i, &terms, max_rating))
+        .map(|(pos, i)| (*i, weight(i, items.len() - pos, by_age, &preferred)))
+        .collect();
+
+    if candidates.is_empty() {
+        return Err(anyhow!("no matching items"));
+    }
+
+    let mut rng = match matches.get_one::<u64>("SEED") {
+        Some(seed) => fastrand::Rng::with_seed(*seed),
+        None => fastrand::Rng::new(),
+    };
+
+    let count = *matches.get_one::<usize>("COUNT").unwrap();
+    let options = media::format::ListOptions {
+        note: true,
+        tags: false,
+        last_seen: false,
+        ids: false,
+        max_rating,
+    };
+    for item in pick(candidates, count, &mut rng) {
+        println!("{}", item.as_line(&options));
+    }
+
+    Ok(())
+}
+
+fn weight(item: &Media, age: usize, by_age: bool, preferred: &[&String]) -> f64 {
+    let mut weight = if by_age { age as f64 } else { 1.0 };
+    for tag in preferred {
+        if item.has_tag(tag) {
+            weight *= 2.0;
+        }
+    }
+    weight
+}
+
+/// Pick up to `count` distinct items, each with a probability proportional to
+/// its weight
+pub fn pick<'a>(
+    mut candidates: Vec<(&'a Media, f64)>,
+    count: usize,
+    rng: &mut fastrand::Rng,
+) -> Vec<&'a Media> {
+    let mut result = vec![];
+    while result.len() < count && !candidates.is_empty() {
+        let total: f64 = candidates.iter().map(|c| c.1).sum();
+        let mut target = rng.f64() * total;
+        let index = candidates
+            .iter()
+            .position(|c| {
+                target -= c.1;
+                target < 0.0
+            })
+            .unwrap_or(candidates.len() - 1);
+        result.push(candidates.remove(index).0);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_distinct_items() {
+        let items = [Media::new("a", None), Media::new("b", None), Media::new("c", None)];
+        let candidates = items.iter().map(|i| (i, 1.0)).collect();
+        let mut picked = pick(candidates, 5, &mut fastrand::Rng::with_seed(1));
+        picked.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(picked.iter().map(|i| i.name.as_str()).collect::<Vec<_>>(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn seed_is_reproducible() {
+        let items: Vec<_> = (0..100).map(|i| Media::new(i.to_string(), None)).collect();
+        let candidates = || items.iter().map(|i| (i, 1.0)).collect();
+        let a = pick(candidates(), 3, &mut fastrand::Rng::with_seed(42));
+        let b = pick(candidates(), 3, &mut fastrand::Rng::with_seed(42));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn respects_weights() {
+        let items = [Media::new("a", None), Media::new("b", None)];
+        let candidates = || vec![(&items[0], 0.0), (&items[1], 1.0)];
+        let mut rng = fastrand::Rng::with_seed(7);
+        for _ in 0..20 {
+            assert_eq!(pick(candidates(), 1, &mut rng)[0].name, "b");
+        }
+    }
+
+    #[test]
+    fn weights_preferred_tags() {
+        let mut m = Media::new("a", None);
+        m.tags = vec!["horror".into()];
+        let horror = String::from("horror");
+        assert_eq!(weight(&m, 5, false, &[]), 1.0);
+        assert_eq!(weight(&m, 5, true, &[]), 5.0);
+        assert_eq!(weight(&m, 5, true, &[&horror]), 10.0);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    list,
+    media::{repo::Repo, Media},
+};
+
+/// Filter for items, using the same terms as `mtracker ls`: tags, parts of
+/// names, years ("2020", "-1999", "2000-2010"), ratings ("+++", "--") and
+/// "rated"/"unrated". Terms prefixed with `!` are negated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    terms: Vec<String>,
+}
+
+impl Query {
+    pub fn new(terms: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Query {
+            terms: terms.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Terms separated by whitespace, e.g.: "horror 2020- !watchlist"
+    pub fn parse(input: &str) -> Self {
+        Self::new(input.split_whitespace())
+    }
+
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// Whether an item matches all terms. Ratings like "--" are relative to
+    /// `max_rating`, see `Repo::max_rating`.
+    pub fn matches(&self, item: &Media, max_rating: u8) -> bool {
+        list::matches_terms(item, &self.terms, max_rating)
+    }
+
+    /// Positions of the matching items of a repo, in order. Tags are looked up
+    /// in the repo's index, but other terms also match parts of names, which
+    /// the handle index can't find, so every item is still checked once.
+    pub fn positions(&self, repo: &Repo) -> Vec<usize> {
+        let max_rating = repo.max_rating();
+
+        // Look up tags in the index instead of checking every item's tags
+        let tagged: Vec<(&str, Vec<usize>)> = self
+            .terms
+            .iter()
+            .map(|t| t.strip_prefix('!').filter(|t| !t.is_empty()).unwrap_or(t))
+            .map(|t| (t, repo.tagged(t)))
+            .collect();
+        let has_tag = |i: usize, tag: &str| {
+            tagged
+                .iter()
+                .any(|(t, positions)| *t == tag && positions.binary_search(&i).is_ok())
+        };
+
+        (0..repo.len())
+            .filter(|&i| {
+                list::matches_terms_with(repo.get_by_index(i), &self.terms, max_rating, |tag| {
+                    has_tag(i, tag)
+                })
+            })
+            .collect()
+    }
+
+    /// The matching items of a repo, in order
+    pub fn filter<'a>(&self, repo: &'a Repo) -> Vec<&'a Media> {
+        self.positions(repo).into_iter().map(|i| repo.get_by_index(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_items() {
+        let mut path = std::env::temp_dir();
+        path.push("mtracker_test_filters_items.txt");
+        std::fs::write(
+            &path,
+            "Alien\nyear: 1979\nrating: 8\ntags: horror/space\n\nPearl\nyear: 2022\ntags: horror\n\nDune\nyear: 2021",
+        )
+        .unwrap();
+        let repo = Repo::new(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let names = |input| -> Vec<String> {
+            Query::parse(input).filter(&repo).iter().map(|m| m.name.clone()).collect()
+        };
+        assert_eq!(names("horror"), ["Alien", "Pearl"]);
+        assert_eq!(names("horror !2022"), ["Alien"]);
+        assert_eq!(names("2020- !horror"), ["Dune"]);
+        assert_eq!(names("rated"), ["Alien"]);
+        assert_eq!(names("ALIEN"), ["Alien"]);
+        assert_eq!(names(""), ["Alien", "Pearl", "Dune"]);
+
+        // Same as without the index
+        let query = Query::parse("horror/space");
+        let matching: Vec<usize> =
+            (0..repo.len()).filter(|&i| query.matches(repo.get_by_index(i), 8)).collect();
+        assert_eq!(query.positions(&repo), matching);
+    }
+}
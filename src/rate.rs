@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::{Arg, ArgMatches, Command};
 
-use crate::{arg_util, args};
+use crate::{arg_util, args, config, media::repo::Repo};
 
 pub fn command() -> Command {
     Command::new("rate")
@@ -16,22 +16,32 @@ pub fn command() -> Command {
                 .help("Rating (number)"),
         )
         .arg(args::year())
+        .arg(args::create())
 }
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
     let mut repo = arg_util::repo_from_matches(matches)?;
+    apply(&mut repo, matches)?;
+    Ok(repo.write()?)
+}
+
+/// Rate an item, without saving
+pub fn apply(repo: &mut Repo, matches: &ArgMatches) -> Result<()> {
     let handle = arg_util::handle_from_matches(matches)?.unwrap();
     let rating = matches.get_one::<u8>("RATING");
 
-    let media = repo.get_or_create(&handle)?;
+    let create = *matches.get_one::<bool>("CREATE").unwrap_or(&false);
+
+    let handle = arg_util::get_or_create(repo, &handle, create)?;
+    let media = repo.get(&handle).unwrap();
 
     media.rating = rating.copied();
     println!("Rated {handle}: {}", rating.unwrap());
 
     if media.on_watchlist() {
-        media.remove_tag("watchlist")?;
+        media.remove_tag(&config::get().watchlist_tag)?;
         println!("Removed from watchlist: {handle}");
     }
 
-    repo.write()
+    Ok(())
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::{ArgMatches, Command};
 
-use crate::{arg_util, args};
+use crate::{arg_util, args, media::repo::Repo};
 
 pub fn command() -> Command {
     Command::new("rm")
@@ -15,8 +15,15 @@ pub fn command() -> Command {
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
     let mut repo = arg_util::repo_from_matches(matches)?;
+    apply(&mut repo, matches)?;
+    Ok(repo.write()?)
+}
+
+/// Remove an item or tags, without saving
+pub fn apply(repo: &mut Repo, matches: &ArgMatches) -> Result<()> {
     let handle = arg_util::handle_from_matches(matches)?.unwrap();
     let tags = arg_util::tags_from_matches(matches);
+    let handle = arg_util::resolve(repo, &handle, false)?.unwrap();
 
     // Remove item
     if tags.is_empty() {
@@ -35,5 +42,5 @@ pub fn handle(matches: &ArgMatches) -> Result<()> {
         })?;
     }
 
-    repo.write()
+    Ok(())
 }
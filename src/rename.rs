@@ -0,0 +1,47 @@
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    arg_util, args,
+    media::{handle::Handle, repo::Repo},
+};
+
+pub fn command() -> Command {
+    Command::new("mv")
+        .visible_aliases(["rename"])
+        .about("Rename item and/or change its year")
+        .arg_required_else_help(true)
+        .arg(args::identifier())
+        .arg(
+            Arg::new("NEW_IDENTIFIER")
+                .required_unless_present("YEAR")
+                .help("New \"name (year)\" or \"name\" [default: the current name]"),
+        )
+        .arg(args::year().help("New year of release"))
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let mut repo = arg_util::repo_from_matches(matches)?;
+    apply(&mut repo, matches)?;
+    Ok(repo.write()?)
+}
+
+/// Rename an item, without saving
+pub fn apply(repo: &mut Repo, matches: &ArgMatches) -> Result<()> {
+    let handle = Handle::from_user_input(matches.get_one::<String>("IDENTIFIER").unwrap());
+    let handle = arg_util::resolve(repo, &handle, false)?.unwrap();
+
+    // The year applies to the new name, e.g. 'mv Alien -y 1979'
+    let mut new_handle = match matches.get_one::<String>("NEW_IDENTIFIER") {
+        Some(new) => Handle::from_user_input(new),
+        None => handle.clone(),
+    };
+    if let Some(year) = matches.get_one::<u16>("YEAR") {
+        new_handle.year = Some(*year);
+    }
+
+    repo.rename(&handle, &new_handle)?;
+    println!("Renamed {handle} to {new_handle}");
+
+    Ok(())
+}
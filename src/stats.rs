@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use clap::{Arg, ArgMatches, Command};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{arg_util, args, list, media::Media};
+
+/// Tags that describe what kind of media an item is
+const KINDS: [&str; 4] = ["movie", "series", "book", "game"];
+
+/// Width of the longest bar in a chart
+const BAR_WIDTH: usize = 40;
+
+/// Number of steps in which the growth of the watchlist is shown
+const GROWTH_STEPS: usize = 10;
+
+pub fn command() -> Command {
+    Command::new("stats")
+        .about("Show statistics")
+        .after_long_help(args::TERM_HELP)
+        .arg_required_else_help(false)
+        .arg(args::term().help("Terms to search for (tag, year)"))
+        .arg(
+            Arg::new("JSON")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("json")
+                .help("Output statistics as JSON"),
+        )
+}
+
+pub fn handle(matches: &ArgMatches) -> Result<()> {
+    let repo = arg_util::repo_from_matches(matches)?;
+    let mut items = repo.get_all();
+
+    // Get max rating BEFORE filtering
+    let max_rating = repo.max_rating();
+
+    let terms = arg_util::terms_from_matches(matches);
+    items.retain(|i| list::matches_terms(i, &terms, max_rating));
+
+    let stats = Stats::from_items(&items);
+
+    if *matches.get_one::<bool>("JSON").unwrap_or(&false) {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", stats.render(max_rating));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub total: usize,
+    pub rated: usize,
+    pub watchlist: usize,
+    /// Size of the watchlist as items were added: (items, watchlist items)
+    pub watchlist_growth: Vec<(usize, usize)>,
+    pub per_rating: BTreeMap<u8, usize>,
+    pub per_tag: Vec<(String, usize)>,
+    pub per_year: BTreeMap<u16, usize>,
+    pub per_decade: BTreeMap<u16, usize>,
+    pub per_kind: BTreeMap<String, usize>,
+    pub seen_per_month: BTreeMap<String, usize>,
+    pub avg_rating_per_tag: Vec<(String, f64)>,
+}
+
+impl Stats {
+    pub fn from_items(items: &[&Media]) -> Self {
+        let mut stats = Stats {
+            total: items.len(),
+            ..Default::default()
+        };
+        let mut tags: HashMap<&str, usize> = HashMap::new();
+        let mut tag_ratings: HashMap<&str, (u32, u32)> = HashMap::new();
+        // Items appear in the order they were added, as in `pick --by-age`
+        let step = items.len().div_ceil(GROWTH_STEPS);
+
+        for (i, item) in items.iter().enumerate() {
+            if let Some(rating) = item.rating {
+                stats.rated += 1;
+                *stats.per_rating.entry(rating).or_default() += 1;
+            }
+            if item.on_watchlist() {
+                stats.watchlist += 1;
+            }
+            if (i + 1) % step == 0 || i + 1 == items.len() {
+                stats.watchlist_growth.push((i + 1, stats.watchlist));
+            }
+            if let Some(year) = item.year {
+                *stats.per_year.entry(year).or_default() += 1;
+                *stats.per_decade.entry(year / 10 * 10).or_default() += 1;
+            }
+            if let Some(date) = item.last_seen {
+                *stats.seen_per_month.entry(date.format("%Y-%m").to_string()).or_default() += 1;
+            }
+
+            let kind = KINDS.iter().find(|k| item.has_tag(k)).copied().unwrap_or("other");
+            *stats.per_kind.entry(kind.into()).or_default() += 1;
+
+            for tag in &item.tags {
+                *tags.entry(tag).or_default() += 1;
+                if let Some(rating) = item.rating {
+                    let sum = tag_ratings.entry(tag).or_default();
+                    sum.0 += u32::from(rating);
+                    sum.1 += 1;
+                }
+            }
+        }
+
+        // Tags, sorted by frequency, then name
+        stats.per_tag = tags.into_iter().map(|(t, n)| (t.to_string(), n)).collect();
+        stats.per_tag.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        // Average ratings, sorted by rating, then name
+        stats.avg_rating_per_tag = tag_ratings
+            .into_iter()
+            .map(|(t, (sum, n))| (t.to_string(), f64::from(sum) / f64::from(n)))
+            .collect();
+        stats
+            .avg_rating_per_tag
+            .sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        stats
+    }
+
+    pub fn render(&self, max_rating: u8) -> String {
+        let mut result = format!(
+            "{} items, {} rated, {} on watchlist\n",
+            self.total, self.rated, self.watchlist
+        );
+
+        if self.watchlist > 0 {
+            result += &chart(
+                "Watchlist by items added",
+                self.watchlist_growth
+                    .iter()
+                    .map(|(i, n)| (i.to_string(), *n as f64, n.to_string())),
+                None,
+            );
+        }
+        result += &chart(
+            "Ratings",
+            self.per_rating
+                .iter()
+                .rev()
+                .map(|(r, n)| (r.to_string(), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            "Tags",
+            self.per_tag.iter().map(|(t, n)| (t.clone(), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            "Kinds",
+            self.per_kind.iter().map(|(k, n)| (k.clone(), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            "Decades",
+            self.per_decade.iter().map(|(d, n)| (format!("{d}s"), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            "Years",
+            self.per_year.iter().map(|(y, n)| (y.to_string(), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            "Seen per month",
+            self.seen_per_month.iter().map(|(m, n)| (m.clone(), *n as f64, n.to_string())),
+            None,
+        );
+        result += &chart(
+            &format!("Average rating per tag (of {max_rating})"),
+            self.avg_rating_per_tag.iter().map(|(t, r)| (t.clone(), *r, format!("{r:.1}"))),
+            Some(f64::from(max_rating)),
+        );
+
+        result
+    }
+}
+
+/// Render a horizontal bar chart from (label, value, displayed value) rows.
+/// Bars are scaled to `scale` if given, otherwise to the highest value.
+fn chart(
+    title: &str,
+    rows: impl Iterator<Item = (String, f64, String)>,
+    scale: Option<f64>,
+) -> String {
+    let rows: Vec<_> = rows.collect();
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let label_width = rows.iter().map(|r| r.0.chars().count()).max().unwrap_or(0);
+    let max = scale.unwrap_or_else(|| rows.iter().map(|r| r.1).fold(0.0, f64::max));
+
+    let mut result = format!("\n{}\n", title.bold());
+    for (label, value, display) in rows {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let width = if max > 0.0 {
+            (value / max * BAR_WIDTH as f64).round() as usize
+        } else {
+            0
+        };
+        result += &format!("{label:<label_width$} {} {display}\n", "█".repeat(width));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, year: Option<u16>, rating: Option<u8>, tags: &[&str]) -> Media {
+        let mut m = Media::new(name, year);
+        m.rating = rating;
+        m.tags = tags.iter().map(|t| t.to_string()).collect();
+        m
+    }
+
+    #[test]
+    fn counts() {
+        let mut seen = item("Alien", Some(1979), Some(4), &["horror", "movie"]);
+        seen.last_seen = chrono::NaiveDate::from_ymd_opt(2024, 6, 12);
+        let items = [
+            seen,
+            item("Aliens", Some(1986), Some(2), &["horror", "movie"]),
+            item("Lost", Some(2004), None, &["series", "watchlist"]),
+            item("Pearl", None, None, &[]),
+        ];
+        let stats = Stats::from_items(&items.iter().collect::<Vec<_>>());
+
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.rated, 2);
+        assert_eq!(stats.watchlist, 1);
+        assert_eq!(stats.watchlist_growth, [(1, 0), (2, 0), (3, 1), (4, 1)]);
+        assert_eq!(stats.per_rating, BTreeMap::from([(2, 1), (4, 1)]));
+        assert_eq!(stats.per_tag[0], ("horror".into(), 2));
+        assert_eq!(stats.per_decade, BTreeMap::from([(1970, 1), (1980, 1), (2000, 1)]));
+        assert_eq!(
+            stats.per_kind,
+            BTreeMap::from([("movie".into(), 2), ("series".into(), 1), ("other".into(), 1)])
+        );
+        assert_eq!(stats.seen_per_month, BTreeMap::from([("2024-06".into(), 1)]));
+        assert_eq!(stats.avg_rating_per_tag[0], ("horror".into(), 3.0));
+    }
+
+    #[test]
+    fn shows_watchlist_growth() {
+        let items: Vec<_> = (0..25)
+            .map(|i| {
+                item(
+                    "Item",
+                    None,
+                    None,
+                    if i < 20 && i % 4 == 0 {
+                        &["watchlist"]
+                    } else {
+                        &[]
+                    },
+                )
+            })
+            .collect();
+        let stats = Stats::from_items(&items.iter().collect::<Vec<_>>());
+        assert_eq!(
+            stats.watchlist_growth,
+            [(3, 1), (6, 2), (9, 3), (12, 3), (15, 4), (18, 5), (21, 5), (24, 5), (25, 5)]
+        );
+        assert!(Stats::from_items(&[]).watchlist_growth.is_empty());
+
+        colored::control::set_override(false);
+        let output = stats.render(10);
+        assert!(output.contains(&format!("\nWatchlist by items added\n3  {} 1\n", "█".repeat(8))));
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(json.contains("\"watchlist_growth\":[[3,1],[6,2],"));
+    }
+
+    #[test]
+    fn chart_scales_bars() {
+        colored::control::set_override(false);
+        let rows = vec![("a".into(), 2.0, "2".into()), ("bb".into(), 1.0, "1".into())];
+        let output = chart("Title", rows.into_iter(), None);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[1], "Title");
+        assert_eq!(lines[2], format!("a  {} 2", "█".repeat(BAR_WIDTH)));
+        assert_eq!(lines[3], format!("bb {} 1", "█".repeat(BAR_WIDTH / 2)));
+    }
+}
@@ -1,19 +1,150 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::Result;
-use clap::{ArgMatches, Command};
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches, Command};
 
-use crate::arg_util;
+use crate::{
+    arg_util, args, list,
+    media::{self, repo::Repo, tag, Media},
+};
 
 pub fn command() -> Command {
     Command::new("tags")
-        .about("List all tags sorted by frequency")
+        .about("List all tags sorted by frequency, or modify tags of many items")
         .arg_required_else_help(false)
+        .arg(
+            Arg::new("TREE")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .num_args(0)
+                .long("tree")
+                .help("Show hierarchical tags (e.g. horror/slasher) as a tree with item counts"),
+        )
+        .subcommand(
+            Command::new("rename")
+                .about("Rename a tag on all items")
+                .arg_required_else_help(true)
+                .arg(Arg::new("OLD").required(true).help("Tag to rename"))
+                .arg(Arg::new("NEW").required(true).help("New name of the tag"))
+                .arg(dry_run()),
+        )
+        .subcommand(
+            Command::new("merge")
+                .about("Merge tags into a single tag on all items")
+                .long_about(
+                    "Merge tags into a single tag on all items
+
+Example: tags merge scifi sci-fi into science-fiction",
+                )
+                .arg_required_else_help(true)
+                .arg(
+                    Arg::new("TAGS")
+                        .required(true)
+                        .num_args(3..)
+                        .value_name("TAG")
+                        .help("Tags to merge, followed by 'into' and the target tag"),
+                )
+                .arg(dry_run()),
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("Remove a tag from all items")
+                .arg_required_else_help(true)
+                .arg(Arg::new("TAG").required(true).help("Tag to delete"))
+                .arg(dry_run()),
+        )
+        .subcommand(
+            Command::new("add")
+                .about("Add a tag to all items matching the search terms")
+                .after_long_help(args::TERM_HELP)
+                .arg_required_else_help(true)
+                .arg(Arg::new("TAG").required(true).help("Tag to add"))
+                .arg(dry_run())
+                .arg(args::term().help("Terms to search for (tag, year), e.g.: -- horror 1980-")),
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Remove a tag from all items matching the search terms")
+                .after_long_help(args::TERM_HELP)
+                .arg_required_else_help(true)
+                .arg(Arg::new("TAG").required(true).help("Tag to remove"))
+                .arg(dry_run())
+                .arg(args::term().help("Terms to search for (tag, year), e.g.: -- horror 1980-")),
+        )
+}
+
+fn dry_run() -> Arg {
+    Arg::new("DRY_RUN")
+        .required(false)
+        .value_parser(clap::value_parser!(bool))
+        .num_args(0)
+        .long("dry-run")
+        .help("Only list affected items, don't save changes")
 }
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
-    let repo = arg_util::repo_from_matches(matches)?;
+    let mut repo = arg_util::repo_from_matches(matches)?;
+
+    let (sub_matches, affected) = match matches.subcommand() {
+        Some(("rename", m)) => {
+            let old = m.get_one::<String>("OLD").unwrap();
+            let new = m.get_one::<String>("NEW").unwrap();
+            (m, update_all(&mut repo, |i| retag(i, &[old], Some(new))))
+        }
+        Some(("merge", m)) => {
+            let (from, into) = parse_merge(m.get_many::<String>("TAGS").unwrap().collect())?;
+            (m, update_all(&mut repo, |i| retag(i, &from, Some(into))))
+        }
+        Some(("delete", m)) => {
+            let tag = m.get_one::<String>("TAG").unwrap();
+            (m, update_all(&mut repo, |i| retag(i, &[tag], None)))
+        }
+        Some(("add", m)) => {
+            let tag = m.get_one::<String>("TAG").unwrap();
+            let terms = arg_util::terms_from_matches(m);
+            let matching = matching(&repo, &terms);
+            (m, update_all(&mut repo, |i| matching(i) && !i.has_tag(tag) && add(i, tag)))
+        }
+        Some(("remove", m)) => {
+            let tag = m.get_one::<String>("TAG").unwrap();
+            let terms = arg_util::terms_from_matches(m);
+            let matching = matching(&repo, &terms);
+            (m, update_all(&mut repo, |i| matching(i) && retag(i, &[tag], None)))
+        }
+        None if *matches.get_one::<bool>("TREE").unwrap_or(&false) => {
+            print!("{}", render_tree(&count_tree(&repo.get_all())));
+            return Ok(());
+        }
+        None => return list_tags(&repo),
+        _ => unreachable!(),
+    };
+
+    // List affected items
+    let options = media::format::ListOptions {
+        note: false,
+        tags: true,
+        last_seen: false,
+        ids: false,
+        max_rating: 0,
+    };
+    for i in &affected {
+        println!("{}", repo.get_by_index(*i).as_line(&options));
+    }
+    println!("{} item(s) affected", affected.len());
+
+    if *sub_matches.get_one::<bool>("DRY_RUN").unwrap_or(&false) {
+        println!("Dry run, no changes saved.");
+        return Ok(());
+    }
+
+    if affected.is_empty() {
+        Ok(())
+    } else {
+        Ok(repo.write()?)
+    }
+}
 
+fn list_tags(repo: &Repo) -> Result<()> {
     // Get list of tags (including duplicates)
     let tags = repo.get_all().into_iter().flat_map(|i| i.tags.clone());
 
@@ -34,3 +165,187 @@ pub fn handle(matches: &ArgMatches) -> Result<()> {
 
     Ok(())
 }
+
+/// Count items per tag, including those tagged with a descendant of the tag
+fn count_tree<'a>(items: &[&'a Media]) -> HashMap<&'a str, usize> {
+    let mut counts = HashMap::new();
+    for item in items {
+        let nodes: HashSet<&str> = item.tags.iter().flat_map(|t| tag::ancestors(t)).collect();
+        for node in nodes {
+            *counts.entry(node).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+fn render_tree(counts: &HashMap<&str, usize>) -> String {
+    fn render_children(
+        counts: &HashMap<&str, usize>,
+        parent: Option<&str>,
+        depth: usize,
+    ) -> String {
+        // Children sorted by frequency, then name
+        let mut children: Vec<_> = counts
+            .iter()
+            .filter(|(node, _)| node.rsplit_once(tag::SEPARATOR).map(|(p, _)| p) == parent)
+            .collect();
+        children.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        let mut result = String::new();
+        for (node, count) in children {
+            let label = node.rsplit(tag::SEPARATOR).next().unwrap_or(node);
+            result += &format!("{}{label} ({count})\n", "  ".repeat(depth));
+            result += &render_children(counts, Some(node), depth + 1);
+        }
+        result
+    }
+
+    render_children(counts, None, 0)
+}
+
+// "a b into c" -> ([a, b], c)
+fn parse_merge(args: Vec<&String>) -> Result<(Vec<&String>, &String)> {
+    match args.as_slice() {
+        [from @ .., into, target] if into.as_str() == "into" && !from.is_empty() => {
+            Ok((from.to_vec(), target))
+        }
+        _ => Err(anyhow!("expected: tags merge <TAG>... into <TAG>")),
+    }
+}
+
+// Returns a predicate for items matching the search terms
+fn matching<'a>(repo: &Repo, terms: &'a [&'a String]) -> impl Fn(&Media) -> bool + 'a {
+    let max_rating = repo.max_rating();
+    move |item| list::matches_terms(item, terms, max_rating)
+}
+
+// Apply `f` to all items, returning the indices of those it changed
+fn update_all(repo: &mut Repo, f: impl Fn(&mut Media) -> bool) -> Vec<usize> {
+    (0..repo.len()).filter(|&i| f(repo.get_by_index_mut(i))).collect()
+}
+
+fn add(item: &mut Media, tag: &str) -> bool {
+    item.add_tag(tag);
+    true
+}
+
+/// Replace the tags `from` with the tag `to`, or remove them if `to` is None.
+/// Aliases of the tags match as well (see `tag::normalize`). Returns whether
+/// the item was changed.
+fn retag(item: &mut Media, from: &[&String], to: Option<&String>) -> bool {
+    let matches = |t: &String| from.iter().any(|f| tag::normalize(f) == tag::normalize(t));
+    let Some(pos) = item.tags.iter().position(matches) else {
+        return false;
+    };
+
+    let before = item.tags.clone();
+    item.tags.retain(|t| !matches(t));
+    if let Some(to) = to {
+        let to = tag::normalize(to);
+        if !item.has_tag(to) {
+            item.tags.insert(pos, to.into());
+        }
+    }
+    item.tags != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(tags: &[&str]) -> Media {
+        let mut m = Media::new("Test", None);
+        m.tags = tags.iter().map(|t| t.to_string()).collect();
+        m
+    }
+
+    #[test]
+    fn retag_renames_in_place() {
+        let (scifi, sf) = (String::from("scifi"), String::from("sci-fi"));
+        let mut m = item(&["horror", "scifi", "funny"]);
+        assert!(retag(&mut m, &[&scifi], Some(&sf)));
+        assert_eq!(m.tags, vec!["horror", "sci-fi", "funny"]);
+
+        let mut m = item(&["horror"]);
+        assert!(!retag(&mut m, &[&scifi], Some(&sf)));
+        assert_eq!(m.tags, vec!["horror"]);
+    }
+
+    #[test]
+    fn retag_merges_without_duplicates() {
+        let (a, b, c) = (String::from("a"), String::from("b"), String::from("c"));
+        let mut m = item(&["a", "x", "b", "c"]);
+        assert!(retag(&mut m, &[&a, &b], Some(&c)));
+        assert_eq!(m.tags, vec!["x", "c"]);
+
+        let mut m = item(&["b", "x", "a"]);
+        assert!(retag(&mut m, &[&a, &b], Some(&c)));
+        assert_eq!(m.tags, vec!["c", "x"]);
+    }
+
+    #[test]
+    fn retag_deletes() {
+        let a = String::from("a");
+        let mut m = item(&["a", "x"]);
+        assert!(retag(&mut m, &[&a], None));
+        assert_eq!(m.tags, vec!["x"]);
+    }
+
+    #[test]
+    fn retag_matches_aliases() {
+        tag::set_aliases(tag::Aliases::parse("mystery: whodunit").unwrap());
+        let (mystery, whodunit) = (String::from("mystery"), String::from("whodunit"));
+        let mut m = item(&["mystery", "x"]);
+        assert!(retag(&mut m, &[&whodunit], None));
+        assert_eq!(m.tags, vec!["x"]);
+
+        let mut m = item(&["whodunit", "x"]);
+        assert!(retag(&mut m, &[&mystery], None));
+        assert_eq!(m.tags, vec!["x"]);
+
+        // Replaced by the canonical form, unless it is there already
+        let thriller = String::from("thriller");
+        let mut m = item(&["thriller", "x"]);
+        assert!(retag(&mut m, &[&thriller], Some(&whodunit)));
+        assert_eq!(m.tags, vec!["mystery", "x"]);
+        let mut m = item(&["whodunit"]);
+        assert!(retag(&mut m, &[&whodunit], Some(&mystery)));
+        assert_eq!(m.tags, vec!["mystery"]);
+        let mut m = item(&["mystery"]);
+        assert!(!retag(&mut m, &[&whodunit], Some(&mystery)));
+    }
+
+    #[test]
+    fn renders_tree() {
+        let items = [
+            item(&["horror/slasher", "horror/slasher/80s"]),
+            item(&["horror"]),
+            item(&["horror/gore", "comedy"]),
+        ];
+        let counts = count_tree(&items.iter().collect::<Vec<_>>());
+        assert_eq!(counts["horror"], 3);
+        assert_eq!(counts["horror/slasher"], 1);
+        assert_eq!(
+            render_tree(&counts),
+            "horror (3)
+  gore (1)
+  slasher (1)
+    80s (1)
+comedy (1)
+"
+        );
+    }
+
+    #[test]
+    fn parses_merge() {
+        let args: Vec<String> = ["a", "b", "into", "c"].iter().map(|s| s.to_string()).collect();
+        let (from, into) = parse_merge(args.iter().collect()).unwrap();
+        assert_eq!(from, vec!["a", "b"]);
+        assert_eq!(into, "c");
+
+        let args: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_merge(args.iter().collect()).is_err());
+        let args: Vec<String> = ["into", "c"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_merge(args.iter().collect()).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{io, path::PathBuf, time::Duration};
+use std::{io, time::Duration};
 
 use anyhow::Result;
 use clap::ArgMatches;
@@ -8,7 +8,7 @@ use crossterm::{
 };
 use ratatui::Terminal;
 
-use crate::media::repo::Repo;
+use crate::{arg_util, config, media::repo::Repo};
 
 mod app;
 mod input;
@@ -17,9 +17,18 @@ mod render;
 use app::{App, Mode};
 
 pub fn run(matches: &ArgMatches) -> Result<()> {
-    let path = PathBuf::from(matches.get_one::<String>("DB").unwrap());
-    let repo = Repo::new(&path)?;
-    let mut app = App::new(repo);
+    // Databases to switch between: all profiles, or just the one given by --db
+    let path = arg_util::db_from_matches(matches)?;
+    let profiles = match matches.get_one::<String>("DB") {
+        Some(db) => vec![(db.clone(), path.clone())],
+        None => config::get().profiles(),
+    };
+    let profile = profiles.iter().position(|p| p.1 == path).unwrap_or(0);
+
+    let lenient = arg_util::lenient_from_matches(matches);
+    let repo = Repo::open(&path, lenient)?;
+    let mut app = App::new(repo, profiles, profile, lenient);
+    app.report_skipped();
 
     // Setup terminal
     terminal::enable_raw_mode()?;
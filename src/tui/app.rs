@@ -1,15 +1,19 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
 use ratatui::widgets::ListState;
 use tui_input::Input;
 
 use crate::{
-    list::matches_term,
     media::{repo::Repo, Media},
+    query::Query,
 };
 
 pub enum Mode {
     Normal,
     Filter,
     Rate(String),
+    Rename,
     Confirm(ConfirmAction),
 }
 
@@ -19,6 +23,9 @@ pub enum ConfirmAction {
 
 pub struct App {
     pub repo: Repo,
+    pub profiles: Vec<(String, PathBuf)>,
+    pub profile: usize,
+    pub lenient: bool,
     pub filtered: Vec<usize>,
     pub selected: usize,
     pub list_state: ListState,
@@ -30,9 +37,17 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(repo: Repo) -> Self {
+    pub fn new(
+        repo: Repo,
+        profiles: Vec<(String, PathBuf)>,
+        profile: usize,
+        lenient: bool,
+    ) -> Self {
         let mut app = App {
             repo,
+            profiles,
+            profile,
+            lenient,
             filtered: vec![],
             selected: 0,
             list_state: ListState::default(),
@@ -47,31 +62,7 @@ impl App {
     }
 
     pub fn apply_filter(&mut self) {
-        let terms: Vec<&str> = self.filter.split_whitespace().collect();
-        let max_rating = (0..self.repo.len())
-            .filter_map(|i| self.repo.get_by_index(i).rating)
-            .max()
-            .unwrap_or(0);
-
-        self.filtered = (0..self.repo.len())
-            .filter(|&i| {
-                let item = self.repo.get_by_index(i);
-                if terms.is_empty() {
-                    return true;
-                }
-                for raw_term in &terms {
-                    let (negated, term) = match raw_term.strip_prefix('!') {
-                        Some(t) if !t.is_empty() => (true, t),
-                        _ => (false, *raw_term),
-                    };
-                    let matched = matches_term(item, term, max_rating);
-                    if matched == negated {
-                        return false;
-                    }
-                }
-                true
-            })
-            .collect();
+        self.filtered = Query::parse(&self.filter).positions(&self.repo);
 
         // Sort: watchlist first, then rating desc, then alphabetical
         self.filtered.sort_by(|&a, &b| {
@@ -98,6 +89,29 @@ impl App {
         }
     }
 
+    /// Switch to the database of the next profile
+    pub fn next_profile(&mut self) -> Result<()> {
+        let profile = (self.profile + 1) % self.profiles.len();
+        self.repo = Repo::open(&self.profiles[profile].1, self.lenient)?;
+        self.profile = profile;
+        self.selected = 0;
+        self.apply_filter();
+        Ok(())
+    }
+
+    /// Show the number of invalid entries skipped with --lenient, if any
+    pub fn report_skipped(&mut self) {
+        let count = self.repo.errors().len();
+        if count > 0 {
+            self.message =
+                Some(format!("Skipped {count} invalid entries, run 'mtracker doctor' for details"));
+        }
+    }
+
+    pub fn profile_name(&self) -> &str {
+        &self.profiles[self.profile].0
+    }
+
     pub fn select(&mut self, idx: usize) {
         self.selected = idx;
         self.list_state.select(Some(idx));
@@ -112,13 +126,10 @@ impl App {
     }
 
     pub fn max_rating(&self) -> u8 {
-        (0..self.repo.len())
-            .filter_map(|i| self.repo.get_by_index(i).rating)
-            .max()
-            .unwrap_or(0)
+        self.repo.max_rating()
     }
 }
 
 fn get_weight(item: &Media) -> usize {
-    item.rating.unwrap_or(0) as usize + 1 + if item.has_tag("watchlist") { 1000 } else { 0 }
+    item.rating.unwrap_or(0) as usize + 1 + if item.on_watchlist() { 1000 } else { 0 }
 }
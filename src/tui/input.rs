@@ -8,7 +8,10 @@ use crossterm::{
 use ratatui::Terminal;
 use tui_input::backend::crossterm::EventHandler;
 
-use crate::media::Media;
+use crate::{
+    config,
+    media::{handle::Handle, Media},
+};
 
 use super::app::{App, ConfirmAction, Mode};
 
@@ -21,10 +24,12 @@ pub fn handle_key(
         Mode::Normal => handle_normal(app, key, terminal),
         Mode::Filter => handle_filter(app, key),
         Mode::Rate(_) => handle_rate(app, key),
+        Mode::Rename => handle_rename(app, key),
         Mode::Confirm(_) => handle_confirm(app, key),
     }
 }
 
+#[allow(clippy::collapsible_match)]
 fn handle_normal(
     app: &mut App,
     key: KeyEvent,
@@ -80,22 +85,49 @@ fn handle_normal(
             app.message = None;
         }
         KeyCode::Char('w') => action_toggle_watchlist(app)?,
+        KeyCode::Char('p') => {
+            if !app.filtered.is_empty() {
+                app.select(fastrand::usize(..app.filtered.len()));
+            }
+        }
         KeyCode::Char('r') => {
             if app.selected_item().is_some() {
                 app.mode = Mode::Rate(String::new());
                 app.message = None;
             }
         }
+        KeyCode::Char('n') => {
+            if let Some(item) = app.selected_item() {
+                app.input = tui_input::Input::new(item.handle().to_string());
+                app.mode = Mode::Rename;
+            }
+        }
         KeyCode::Char('a') => {
             action_add(app, terminal)?;
         }
         KeyCode::Char('d') => {
             if let Some(idx) = app.selected_repo_index() {
-                app.mode = Mode::Confirm(ConfirmAction::Delete(idx));
-                app.message = None;
+                if config::get().tui.confirm_delete {
+                    app.mode = Mode::Confirm(ConfirmAction::Delete(idx));
+                    app.message = None;
+                } else {
+                    delete(app, idx)?;
+                }
             }
         }
         KeyCode::Char('e') => action_edit(app, terminal)?,
+        KeyCode::Tab if app.profiles.len() > 1 => {
+            let result = app.next_profile();
+            // Redraw everything, the passphrase of an encrypted database may have been asked for
+            terminal.clear()?;
+            match result {
+                Ok(()) => {
+                    app.message = Some(format!("Switched to profile: {}", app.profile_name()));
+                    app.report_skipped();
+                }
+                Err(e) => app.message = Some(format!("Failed to switch profile: {e}")),
+            }
+        }
         _ => {}
     }
     Ok(())
@@ -147,7 +179,7 @@ fn handle_rate(app: &mut App, key: KeyEvent) -> Result<()> {
                     app.message = Some(format!("Unrated {name}"));
                 } else if let Ok(rating) = input.parse::<u8>() {
                     item.rating = Some(rating);
-                    let _ = item.remove_tag("watchlist");
+                    let _ = item.remove_tag(&config::get().watchlist_tag);
                     app.repo.write()?;
                     app.apply_filter();
                     app.message = Some(format!("Rated {name}: {rating}"));
@@ -172,16 +204,39 @@ fn handle_rate(app: &mut App, key: KeyEvent) -> Result<()> {
     Ok(())
 }
 
+fn handle_rename(app: &mut App, key: KeyEvent) -> Result<()> {
+    match key.code {
+        KeyCode::Esc => {
+            app.mode = Mode::Normal;
+        }
+        _ if is_enter(&key) => {
+            if let Some(item) = app.selected_item() {
+                let handle = item.handle();
+                let new_handle = Handle::from_user_input(app.input.value());
+                match app.repo.rename(&handle, &new_handle) {
+                    Ok(()) => {
+                        app.repo.write()?;
+                        app.apply_filter();
+                        app.message = Some(format!("Renamed {handle} to {new_handle}"));
+                    }
+                    Err(e) => app.message = Some(format!("Rename failed: {e}")),
+                }
+            }
+            app.mode = Mode::Normal;
+        }
+        _ => {
+            app.input.handle_event(&Event::Key(key));
+        }
+    }
+    Ok(())
+}
+
 fn handle_confirm(app: &mut App, key: KeyEvent) -> Result<()> {
     match key.code {
         KeyCode::Char('y') | KeyCode::Char('d') => {
             let action = std::mem::replace(&mut app.mode, Mode::Normal);
             if let Mode::Confirm(ConfirmAction::Delete(idx)) = action {
-                let name = app.repo.get_by_index(idx).name.clone();
-                app.repo.remove_by_index(idx);
-                app.repo.write()?;
-                app.apply_filter();
-                app.message = Some(format!("Deleted {name}"));
+                delete(app, idx)?;
             }
         }
         KeyCode::Char('n') | KeyCode::Esc => {
@@ -192,15 +247,25 @@ fn handle_confirm(app: &mut App, key: KeyEvent) -> Result<()> {
     Ok(())
 }
 
+fn delete(app: &mut App, idx: usize) -> Result<()> {
+    let name = app.repo.get_by_index(idx).name.clone();
+    app.repo.remove_by_index(idx);
+    app.repo.write()?;
+    app.apply_filter();
+    app.message = Some(format!("Deleted {name}"));
+    Ok(())
+}
+
 fn action_toggle_watchlist(app: &mut App) -> Result<()> {
     if let Some(idx) = app.selected_repo_index() {
         let item = app.repo.get_by_index_mut(idx);
         let name = item.name.clone();
-        if item.has_tag("watchlist") {
-            let _ = item.remove_tag("watchlist");
+        let watchlist = &config::get().watchlist_tag;
+        if item.has_tag(watchlist) {
+            let _ = item.remove_tag(watchlist);
             app.message = Some(format!("Removed from watchlist: {name}"));
         } else {
-            item.add_tag("watchlist");
+            item.add_tag(watchlist);
             app.message = Some(format!("Added to watchlist: {name}"));
         }
         app.repo.write()?;
@@ -257,20 +322,17 @@ fn action_add(
                 return Ok(());
             }
 
-            match Media::from_db_entry(&cleaned) {
+            match Media::parse(&cleaned) {
                 Ok(item) => {
-                    let handle = crate::media::handle::Handle {
-                        name: item.name.clone(),
-                        year: item.year,
-                    };
-                    if app.repo.get(&handle).is_some() {
+                    let handle = item.handle();
+                    if app.repo.contains(&handle) {
                         app.message = Some(format!("Already exists: {handle}"));
+                    } else if let Err(e) = app.repo.add(item) {
+                        app.message = Some(format!("Error: {e}"));
                     } else {
-                        let name = format!("{handle}");
-                        app.repo.add(item)?;
                         app.repo.write()?;
                         app.apply_filter();
-                        app.message = Some(format!("Added {name}"));
+                        app.message = Some(format!("Added {handle}"));
                     }
                 }
                 Err(e) => {
@@ -293,36 +355,31 @@ fn action_edit(
         return Ok(());
     };
     let item = app.repo.get_by_index(idx);
+    let handle = item.handle();
     let db_entry = item.to_db_entry();
 
     terminal::disable_raw_mode()?;
     crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
-    let result = edit::edit(&db_entry);
+    let result = crate::edit::edit_entry(&db_entry);
 
     crossterm::execute!(terminal.backend_mut(), EnterAlternateScreen)?;
     terminal::enable_raw_mode()?;
     terminal.clear()?;
 
     match result {
-        Ok(edited) => {
-            if edited == db_entry {
-                app.message = Some("No changes".into());
-                return Ok(());
-            }
-            match Media::from_db_entry(&edited) {
-                Ok(new_item) => {
-                    let name = new_item.name.clone();
-                    app.repo.remove_by_index(idx);
-                    app.repo.add(new_item)?;
+        Ok(None) => app.message = Some("Aborted".into()),
+        Ok(Some((edited, _))) if edited == db_entry => app.message = Some("No changes".into()),
+        Ok(Some((_, new_item))) => {
+            let name = new_item.name.clone();
+            match app.repo.replace(&handle, new_item) {
+                Ok(()) => {
                     app.repo.write()?;
                     app.apply_filter();
                     app.message = Some(format!("Updated {name}"));
                 }
-                Err(e) => {
-                    app.message = Some(format!("Parse error: {e}"));
-                }
+                Err(e) => app.message = Some(format!("Update failed: {e}")),
             }
         }
         Err(e) => {
@@ -6,6 +6,7 @@ use ratatui::{
 };
 
 use super::app::{App, ConfirmAction, Mode};
+use crate::config;
 
 pub fn render(app: &mut App, f: &mut ratatui::Frame) {
     let chunks = Layout::vertical([
@@ -23,13 +24,19 @@ pub fn render(app: &mut App, f: &mut ratatui::Frame) {
     } else {
         String::new()
     };
+    let profile_display = if app.profiles.len() > 1 {
+        format!("  [{}]", app.profile_name())
+    } else {
+        String::new()
+    };
     let title = Paragraph::new(Line::from(vec![
         Span::styled("mtracker", Style::default().add_modifier(Modifier::BOLD)),
-        Span::raw(format!("  ({} items){filter_display}", app.filtered.len())),
+        Span::raw(format!("{profile_display}  ({} items){filter_display}", app.filtered.len())),
     ]));
     f.render_widget(title, chunks[0]);
 
     // List
+    let config = config::get();
     let max_rating = app.max_rating();
     let items: Vec<ListItem> = app
         .filtered
@@ -72,8 +79,9 @@ pub fn render(app: &mut App, f: &mut ratatui::Frame) {
             }
 
             // Tags (excluding watchlist)
-            let tags: Vec<&String> = item.tags.iter().filter(|t| *t != "watchlist").collect();
-            if !tags.is_empty() {
+            let tags: Vec<&String> =
+                item.tags.iter().filter(|t| **t != config.watchlist_tag).collect();
+            if config.tui.show_tags && !tags.is_empty() {
                 spans.push(Span::styled(
                     format!(
                         " [{}]",
@@ -84,7 +92,7 @@ pub fn render(app: &mut App, f: &mut ratatui::Frame) {
             }
 
             // Note
-            if !item.note.is_empty() {
+            if config.tui.show_notes && !item.note.is_empty() {
                 spans.push(Span::styled(
                     format!(": {}", item.note),
                     Style::default().fg(Color::DarkGray),
@@ -110,29 +118,17 @@ pub fn render(app: &mut App, f: &mut ratatui::Frame) {
             let text = if let Some(msg) = &app.message {
                 msg.clone()
             } else {
-                "[/]filter [a]dd [r]ate [e]dit [d]elete [w]atchlist [q]uit".into()
+                "[/]filter [a]dd [r]ate [e]dit re[n]ame [d]elete [w]atchlist [p]ick [q]uit".into()
             };
             Line::from(Span::raw(text))
         }
-        Mode::Filter => {
-            let val = app.input.value();
-            let cur = app.input.cursor();
-            let (before, rest) = val.split_at(cur);
-            let mut chars = rest.chars();
-            let under = chars.next().map_or(" ".to_string(), |c| c.to_string());
-            let after: String = chars.collect();
-            Line::from(vec![
-                Span::styled("Filter: ", yellow),
-                Span::styled(before.to_string(), yellow),
-                Span::styled(under, cursor_style),
-                Span::styled(format!("{after}  (Enter to apply, Esc to clear)"), yellow),
-            ])
-        }
+        Mode::Filter => input_line(app, "Filter: ", "  (Enter to apply, Esc to clear)"),
         Mode::Rate(input) => Line::from(vec![
             Span::styled(format!("Rating: {input}"), yellow),
             Span::styled(" ", cursor_style),
             Span::styled("  (Enter to confirm, Esc to cancel)", yellow),
         ]),
+        Mode::Rename => input_line(app, "Rename: ", "  (Enter to confirm, Esc to cancel)"),
         Mode::Confirm(ConfirmAction::Delete(idx)) => {
             let name = &app.repo.get_by_index(*idx).name;
             Line::from(Span::styled(format!("Delete \"{name}\"? [y/n]"), yellow))
@@ -140,3 +136,23 @@ pub fn render(app: &mut App, f: &mut ratatui::Frame) {
     };
     f.render_widget(Paragraph::new(footer_line), chunks[2]);
 }
+
+// Footer line with the text input and its cursor
+fn input_line<'a>(app: &App, label: &'a str, hint: &'a str) -> Line<'a> {
+    let yellow = Style::default().fg(Color::Yellow);
+    let cursor_style = yellow.add_modifier(Modifier::REVERSED);
+
+    // The cursor counts characters, not bytes
+    let val = app.input.value();
+    let cur = val.char_indices().nth(app.input.cursor()).map_or(val.len(), |(i, _)| i);
+    let (before, rest) = val.split_at(cur);
+    let mut chars = rest.chars();
+    let under = chars.next().map_or(" ".to_string(), |c| c.to_string());
+    let after: String = chars.collect();
+    Line::from(vec![
+        Span::styled(label, yellow),
+        Span::styled(before.to_string(), yellow),
+        Span::styled(under, cursor_style),
+        Span::styled(format!("{after}{hint}"), yellow),
+    ])
+}
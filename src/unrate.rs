@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::{ArgMatches, Command};
 
-use crate::{arg_util, args};
+use crate::{arg_util, args, media::repo::Repo};
 
 pub fn command() -> Command {
     Command::new("unrate")
@@ -14,12 +14,19 @@ pub fn command() -> Command {
 
 pub fn handle(matches: &ArgMatches) -> Result<()> {
     let mut repo = arg_util::repo_from_matches(matches)?;
+    apply(&mut repo, matches)?;
+    Ok(repo.write()?)
+}
+
+/// Remove the rating of an item, without saving
+pub fn apply(repo: &mut Repo, matches: &ArgMatches) -> Result<()> {
     let handle = arg_util::handle_from_matches(matches)?.unwrap();
 
-    let media = repo.get_or_create(&handle)?;
+    let handle = arg_util::resolve(repo, &handle, false)?.unwrap();
+    let media = repo.get(&handle).unwrap();
 
     media.rating = None;
     println!("Removed rating from: {handle}");
 
-    repo.write()
+    Ok(())
 }